@@ -0,0 +1,106 @@
+//! Rolling back everything written by a writer, either to abandon it or to start again.
+
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// A handle that workers can poll to find out whether the writer has been aborted, so that they can
+/// stop filling their shards early. Created by `VecWriter::abort_signal`.
+#[derive(Clone)]
+pub struct AbortSignal {
+    aborted: Arc<AtomicBool>,
+}
+
+impl AbortSignal {
+    /// Returns whether `VecWriter::abort` has been called.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Returns a handle that workers can use to check whether the writer has been aborted.
+    pub fn abort_signal(&mut self) -> AbortSignal {
+        self.abort_signal
+            .get_or_insert_with(|| AbortSignal {
+                aborted: Arc::new(AtomicBool::new(false)),
+            })
+            .clone()
+    }
+
+    /// Drops everything written since the writer was created and restores the vec to its original
+    /// length, e.g. because a worker hit an unrecoverable error. Workers holding an `AbortSignal`
+    /// are told to stop. Outstanding shards drop their own values when they're dropped, and the vec
+    /// remains borrowed until they are.
+    pub fn abort(mut self) {
+        if let Some(check) = self.finish_check.as_mut() {
+            check.finished();
+        }
+        if let Some(signal) = &self.abort_signal {
+            signal.aborted.store(true, Ordering::Relaxed);
+        }
+        self.drop_written();
+    }
+
+    /// Drops everything written since the writer was created and returns the writer to its
+    /// initial state, so that the same capacity can be filled again without reallocating, e.g.
+    /// when rebuilding the same buffer repeatedly. Panics if any shards are outstanding.
+    #[track_caller]
+    pub fn reset(&mut self) {
+        assert!(
+            !self.has_outstanding_shards(),
+            "Can't reset while shards are outstanding"
+        );
+        assert!(
+            !self.has_frozen_views(),
+            "Can't reset while views of returned shards exist"
+        );
+        self.drop_written();
+        self.taken = self.original_len;
+        self.back_taken = 0;
+        self.patches.clear();
+        self.boundaries.clear();
+        self.closed = false;
+        if let Some(check) = self.finish_check.as_mut() {
+            check.reset();
+        }
+        if let Some(notifier) = &self.return_notifier {
+            notifier.notify_returned_up_to(self.original_len);
+        }
+        if let Some(progress) = &self.progress {
+            progress.remove_written(progress.written());
+        }
+        self.update_progress_total();
+    }
+
+    /// Drops the values written by returned shards, including those returned after a gap, and by
+    /// `push`, then restores the vec's original length. If views created by `return_and_freeze`
+    /// still exist, the values are leaked instead, since they may still be in use.
+    fn drop_written(&mut self) {
+        let len = self.storage.len();
+        let ptr = self.storage.as_mut_ptr();
+        // Safety: Shrinking the length is always safe. We set it before dropping values so that
+        // if a drop panics, the vec won't contain dropped values.
+        unsafe { self.storage.set_len(self.original_len) };
+        if self.has_frozen_views() {
+            return;
+        }
+        let underfilled = std::mem::take(&mut self.underfilled)
+            .into_iter()
+            .map(|(start, (written_end, _))| (start, written_end));
+        let written = std::iter::once((self.original_len, len))
+            .chain(std::mem::take(&mut self.pending))
+            .chain(underfilled);
+        for (start, end) in written {
+            // Safety: Everything between the original length and the length was written by shards
+            // that have been returned or by `push`, as were the contents of pending shards and the
+            // written parts of underfilled shards. None of these values are referenced by anything
+            // else.
+            unsafe {
+                std::ptr::slice_from_raw_parts_mut(ptr.add(start), end - start).drop_in_place();
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Shards whose size is known at compile time.
+
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+
+/// A shard of exactly `N` elements, none of which have been written. Since the size is known at
+/// compile time, it can be filled with a single array write without any capacity checks. Created
+/// by `VecWriter::take_array_shard`.
+pub struct ArrayShard<'vec, T, const N: usize> {
+    shard: Shard<'vec, T>,
+}
+
+impl<'vec, T: 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Takes the next `N` elements of the vector as an `ArrayShard`. Panics if there is
+    /// insufficient capacity.
+    #[track_caller]
+    pub fn take_array_shard<const N: usize>(&mut self) -> ArrayShard<'vec, T, N> {
+        ArrayShard {
+            shard: self.take_shard(N),
+        }
+    }
+
+    /// As for `take_array_shard`, but returns None if there is insufficient capacity.
+    pub fn try_take_array_shard<const N: usize>(&mut self) -> Option<ArrayShard<'vec, T, N>> {
+        Some(ArrayShard {
+            shard: self.try_take_shard(N)?,
+        })
+    }
+}
+
+impl<'vec, T, const N: usize> ArrayShard<'vec, T, N> {
+    /// Writes all elements of the shard, returning a full shard that can be returned to the writer.
+    pub fn write_all(mut self, values: [T; N]) -> Shard<'vec, T> {
+        let start = self.shard.initialised_up_to;
+        // Safety: The shard has exactly `N` unwritten elements starting at `start`, which nothing
+        // else aliases. Arrays have the same layout as `N` consecutive elements.
+        unsafe { self.shard.storage.add(start).cast::<[T; N]>().write(values) };
+        self.shard.mark_written(N);
+        self.shard
+    }
+
+    /// Converts into an ordinary shard, e.g. to fill it incrementally.
+    pub fn into_shard(self) -> Shard<'vec, T> {
+        self.shard
+    }
+
+    /// Returns the offset in the output vector at which the shard starts.
+    pub fn start_offset(&self) -> usize {
+        self.shard.start_offset
+    }
+}
@@ -0,0 +1,109 @@
+//! Returning shards from async tasks, without blocking the task that owns the writer.
+
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::task::Waker;
+
+/// As for `ShardReturner`, but the shards are collected by awaiting `VecWriter::collect_async`.
+pub struct AsyncShardReturner<'vec, T> {
+    shared: Arc<Mutex<Shared<'vec, T>>>,
+}
+
+/// Receives shards sent via `AsyncShardReturner`s. Passed to `VecWriter::collect_async`.
+pub struct AsyncShardCollector<'vec, T> {
+    shared: Arc<Mutex<Shared<'vec, T>>>,
+}
+
+struct Shared<'vec, T> {
+    /// Shards that have been sent, but not yet returned to the writer.
+    shards: Vec<Shard<'vec, T>>,
+
+    /// The number of returners that haven't yet been dropped.
+    returners: usize,
+
+    /// Set while the collector is waiting for a shard.
+    waker: Option<Waker>,
+}
+
+/// Creates a returner that async tasks can use to return shards and a collector that the owner of
+/// the writer passes to `VecWriter::collect_async`. Doesn't depend on any particular runtime.
+pub fn async_return_channel<'a, T>() -> (AsyncShardReturner<'a, T>, AsyncShardCollector<'a, T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        shards: Vec::new(),
+        returners: 1,
+        waker: None,
+    }));
+    (
+        AsyncShardReturner {
+            shared: shared.clone(),
+        },
+        AsyncShardCollector { shared },
+    )
+}
+
+impl<'vec, T> AsyncShardReturner<'vec, T> {
+    /// Sends a shard to be returned to the writer by `VecWriter::collect_async`. Never blocks.
+    pub fn return_shard(&self, shard: Shard<'vec, T>) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.shards.push(shard);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for AsyncShardReturner<'_, T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().unwrap().returners += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for AsyncShardReturner<'_, T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.returners -= 1;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<'vec, T> VecWriter<'vec, T> {
+    /// Returns shards sent via `AsyncShardReturner`s as they arrive, resolving once all shards that
+    /// were taken have been returned. As for `collect`, resolves to an error if a shard can't be
+    /// returned or if all returners are dropped before all shards have been returned.
+    pub async fn collect_async(
+        &mut self,
+        collector: AsyncShardCollector<'vec, T>,
+    ) -> Result<(), InitError> {
+        while !self.all_returned() {
+            let shards = poll_fn(|cx| {
+                let mut shared = collector.shared.lock().unwrap();
+                if !shared.shards.is_empty() {
+                    return Poll::Ready(Some(std::mem::take(&mut shared.shards)));
+                }
+                if shared.returners == 0 {
+                    return Poll::Ready(None);
+                }
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            })
+            .await;
+            let Some(shards) = shards else {
+                return Err(self.missing_shard_error());
+            };
+            for shard in shards {
+                self.try_return_shard(shard)?;
+            }
+        }
+        Ok(())
+    }
+}
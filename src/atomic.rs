@@ -0,0 +1,70 @@
+use crate::parallel::available_threads;
+use crate::parallel::map_in_parallel;
+use crate::VecWriter;
+
+/// An atomic integer type, such as `AtomicU32`, that can be constructed from its plain integer
+/// value.
+pub trait AtomicInteger: Send + Sync + Sized {
+    /// The corresponding plain integer type.
+    type Value;
+
+    /// Creates a new atomic with the specified initial value.
+    fn new(value: Self::Value) -> Self;
+}
+
+macro_rules! impl_atomic_integer {
+    ($($width:literal: $atomic:ident($value:ty),)*) => {
+        $(
+            #[cfg(target_has_atomic = $width)]
+            impl AtomicInteger for std::sync::atomic::$atomic {
+                type Value = $value;
+
+                fn new(value: $value) -> Self {
+                    Self::new(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_integer! {
+    "8": AtomicU8(u8),
+    "8": AtomicI8(i8),
+    "16": AtomicU16(u16),
+    "16": AtomicI16(i16),
+    "32": AtomicU32(u32),
+    "32": AtomicI32(i32),
+    "64": AtomicU64(u64),
+    "64": AtomicI64(i64),
+    "ptr": AtomicUsize(usize),
+    "ptr": AtomicIsize(isize),
+}
+
+/// Builds a `Vec` of `len` atomics, where the initial value at each index is `f(index)`. The vec
+/// is split into shards that are filled in parallel. Since nothing else can see the atomics until
+/// the vec is returned, initialising them compiles to plain stores.
+pub fn par_atomic_vec<A: AtomicInteger>(
+    len: usize,
+    f: impl Fn(usize) -> A::Value + Sync,
+) -> Vec<A> {
+    let mut output = Vec::with_capacity(len);
+    let mut writer = VecWriter::new(&mut output);
+    let shard_size = len.div_ceil(available_threads()).max(1);
+    let mut work = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let size = shard_size.min(len - start);
+        work.push((start, writer.take_shard(size)));
+        start += size;
+    }
+    let shards = map_in_parallel(work, |(start, mut shard)| {
+        for index in start..start + shard_size.min(len - start) {
+            shard.push(A::new(f(index)));
+        }
+        shard
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    output
+}
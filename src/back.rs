@@ -0,0 +1,80 @@
+//! Taking shards from the end of the capacity as well as the start, e.g. for partitioning, where
+//! matching values are written to the front and other values to the back.
+
+use crate::trace;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::marker::PhantomData;
+
+impl<'vec, T: 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Takes the last `n` elements of the capacity that hasn't yet been taken or panics if there
+    /// is insufficient capacity. Shards taken from the back are returned in the same way as those
+    /// taken from the front, but don't become part of the vec until the regions taken from the
+    /// front and the back meet, so all capacity must be taken before finishing.
+    #[track_caller]
+    pub fn take_shard_back(&mut self, n: usize) -> Shard<'vec, T> {
+        self.try_take_shard_back(n).unwrap_or_else(|| {
+            panic!(
+                "Tried to take {n} when only {} available",
+                self.remaining_capacity()
+            );
+        })
+    }
+
+    /// As for `take_shard_back`, but returns None if there is insufficient capacity.
+    pub fn try_take_shard_back(&mut self, n: usize) -> Option<Shard<'vec, T>> {
+        if n > self.remaining_capacity() {
+            return None;
+        }
+        let start_offset = self.back_start() - n;
+        let shard = Shard {
+            storage: self.storage.as_mut_ptr(),
+            writer_id: self.id,
+            start_offset,
+            initialised_up_to: start_offset,
+            end_offset: start_offset + n,
+            patches: Vec::new(),
+            published: None,
+            progress: self.progress.clone(),
+            _phantom: PhantomData,
+        };
+        self.back_taken += n;
+        if let Some(check) = self.finish_check.as_mut() {
+            check.shard_taken();
+        }
+        trace::shard_taken(&shard.range());
+        self.join_back_if_met();
+        Some(shard)
+    }
+
+    /// Returns the offset of the start of the region taken from the back.
+    pub(crate) fn back_start(&self) -> usize {
+        self.storage.capacity() - self.back_taken
+    }
+
+    /// Once the regions taken from the front and the back meet, treats everything as having been
+    /// taken from the front.
+    pub(crate) fn join_back_if_met(&mut self) {
+        if self.back_taken == 0 || self.taken != self.back_start() {
+            return;
+        }
+        self.taken = self.storage.capacity();
+        self.back_taken = 0;
+        // If everything taken from the front has already been returned, shards that were returned
+        // to the back can now become part of the vec.
+        let len = self.storage.len();
+        let mut new_len = len;
+        while let Some(end) = self.pending.remove(&new_len) {
+            new_len = end;
+        }
+        if new_len != len {
+            // Safety: All values between the previous length and the new length were written by
+            // shards that have been returned.
+            unsafe { self.storage.set_len(new_len) };
+            if let Some(notifier) = &self.return_notifier {
+                notifier.notify_returned_up_to(new_len);
+            }
+        }
+    }
+}
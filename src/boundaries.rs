@@ -0,0 +1,119 @@
+use crate::parallel::for_each_in_parallel;
+use crate::InitError;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Display;
+use std::ops::Range;
+
+/// The ranges of the output covered by each shard that was returned to a writer, in the order of
+/// their ranges. Can be used to split the finished vec back up along the same boundaries, e.g. for
+/// a second parallel pass over the results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Boundaries {
+    ranges: Vec<Range<usize>>,
+}
+
+impl<T, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Checks that all shards that were taken have been returned, then returns the boundaries of
+    /// the returned shards.
+    pub fn finish(mut self) -> Result<Boundaries, InitError> {
+        if let Some(check) = self.finish_check.as_mut() {
+            check.finished();
+        }
+        if !self.all_returned() {
+            return Err(self.missing_shard_error());
+        }
+        let mut ranges = self.boundaries;
+        ranges.sort_unstable_by_key(|range| range.start);
+        Ok(Boundaries { ranges })
+    }
+
+    /// Returns whether all capacity has been taken and all shards have been returned, or a partial
+    /// shard has been returned after all other shards.
+    pub fn is_complete(&self) -> bool {
+        self.all_returned() && self.remaining_capacity() == 0
+    }
+
+    /// As for `finish`, but also checks that all capacity was taken, so that a missing final shard
+    /// can't silently produce a short vec.
+    pub fn finish_complete(mut self) -> Result<Boundaries, FinishError> {
+        if let Some(check) = self.finish_check.as_mut() {
+            check.finished();
+        }
+        let missing = if !self.all_returned() {
+            self.missing_range()
+        } else {
+            self.taken..self.taken + self.remaining_capacity()
+        };
+        if !missing.is_empty() {
+            return Err(FinishError { missing });
+        }
+        Ok(self.finish().expect("All shards were returned"))
+    }
+}
+
+impl Boundaries {
+    /// Returns the range of the output covered by each shard, in order.
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// Returns the index of the shard whose range contains `offset`, or None if no shard does,
+    /// e.g. because `offset` is in values that were in the vec before the writer was created.
+    pub fn shard_containing(&self, offset: usize) -> Option<usize> {
+        let index = self.ranges.partition_point(|range| range.end <= offset);
+        self.ranges
+            .get(index)
+            .filter(|range| range.contains(&offset))
+            .map(|_| index)
+    }
+
+    /// Splits `values` into one mutable slice per shard. Panics if `values` is shorter than the
+    /// end of the last shard. Any values before the first shard, e.g. that were in the vec before
+    /// the writer was created, aren't included.
+    #[track_caller]
+    pub fn split_mut<'a, T>(&self, mut values: &'a mut [T]) -> Vec<&'a mut [T]> {
+        let end = self.ranges.last().map_or(0, |range| range.end);
+        assert!(
+            values.len() >= end,
+            "Boundaries extend to {end}, but only {} values supplied",
+            values.len()
+        );
+        let mut offset = 0;
+        self.ranges
+            .iter()
+            .map(|range| {
+                let (_, rest) = core::mem::take(&mut values).split_at_mut(range.start - offset);
+                let (chunk, rest) = rest.split_at_mut(range.len());
+                values = rest;
+                offset = range.end;
+                chunk
+            })
+            .collect()
+    }
+
+    /// Calls `f` on each shard's values in parallel, together with the index of the shard. Shards
+    /// are grouped between threads in the same way as for other parallel operations in this crate.
+    /// Panics if `values` is shorter than the end of the last shard.
+    #[track_caller]
+    pub fn par_for_each_mut<T: Send>(&self, values: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+        for_each_in_parallel(
+            self.split_mut(values).into_iter().enumerate().collect(),
+            |(index, chunk)| f(index, chunk),
+        );
+    }
+}
+
+/// The output wasn't complete when the writer was finished.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FinishError {
+    /// The first range of the output that wasn't taken or whose shard wasn't returned.
+    pub missing: Range<usize>,
+}
+impl Error for FinishError {}
+impl Display for FinishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Output incomplete: {:?} not written", self.missing)
+    }
+}
@@ -0,0 +1,122 @@
+use crate::FinishError;
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+/// Like `VecWriter`, but owns the vec that it builds, so it can be stored in a struct or moved
+/// into a task without borrowing anything. Shards have a `'static` lifetime. If the builder is
+/// dropped while shards are outstanding, the vec is leaked so that those shards remain valid.
+pub struct VecBuilder<T: 'static> {
+    /// Borrows `storage`. Must be dropped before `storage` is freed.
+    writer: ManuallyDrop<VecWriter<'static, T>>,
+
+    /// Allocated with `Box::into_raw` and only freed once `writer` has been dropped and no shards
+    /// are outstanding.
+    storage: *mut Vec<T>,
+
+    /// The capacity requested when the builder was created. The vec may have more, e.g. for
+    /// zero-sized types.
+    capacity: usize,
+}
+
+// Safety: We own the storage, which is only accessed via the writer or via shards.
+unsafe impl<T: Send> Send for VecBuilder<T> {}
+
+impl<T> VecBuilder<T> {
+    /// Creates a builder for a vec with capacity for `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let storage = Box::into_raw(Box::new(Vec::with_capacity(capacity)));
+        // Safety: `storage` was just allocated and won't be freed until the writer is dropped.
+        let writer = VecWriter::new(unsafe { &mut *storage });
+        Self {
+            writer: ManuallyDrop::new(writer),
+            storage,
+            capacity,
+        }
+    }
+
+    /// Takes the next `n` elements of the vector or panics if there is insufficient capacity.
+    pub fn take_shard(&mut self, n: usize) -> Shard<'static, T> {
+        self.writer.take_shard(n)
+    }
+
+    /// Takes the next `n` elements of the vector or returns None if there is insufficient capacity.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<Shard<'static, T>> {
+        self.writer.try_take_shard(n)
+    }
+
+    /// Returns a shard to the vector. See `VecWriter::return_shard`. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: Shard<T>) {
+        self.writer.return_shard(shard)
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        self.writer.try_return_shard(shard)
+    }
+
+    /// Checks that all shards that were taken have been returned, then returns the vec. On
+    /// failure, the vec is leaked, since the outstanding shards still point into it.
+    pub fn finish(mut self) -> Result<Vec<T>, InitError> {
+        if self.has_outstanding_shards() {
+            return Err(self.writer.missing_shard_error());
+        }
+        // Safety: We forget `self` below, so the writer won't be dropped again.
+        drop(unsafe { ManuallyDrop::take(&mut self.writer) });
+        // Safety: The writer that borrowed the storage is gone and there are no outstanding
+        // shards.
+        let storage = unsafe { Box::from_raw(self.storage) };
+        std::mem::forget(self);
+        Ok(*storage)
+    }
+
+    /// As for `finish`, but also checks that all of the capacity requested when the builder was
+    /// created has been written, then returns the output as a boxed slice. This normally doesn't
+    /// reallocate, but the vec may have been given more capacity than was requested, in which case
+    /// it's shrunk to fit, which may reallocate and copy. On failure, the vec is leaked if any
+    /// shards are outstanding, otherwise it's dropped.
+    pub fn finish_boxed(self) -> Result<Box<[T]>, FinishError> {
+        self.check_complete()?;
+        let values = self.finish().expect("All shards were returned");
+        Ok(values.into_boxed_slice())
+    }
+
+    /// As for `finish_boxed`, but returns the output as an `Arc<[T]>`, so that it can be shared
+    /// between threads once built. Since an `Arc` keeps its reference counts in the same allocation
+    /// as the values, the values are moved once into a new allocation.
+    pub fn finish_shared(self) -> Result<Arc<[T]>, FinishError> {
+        Ok(Arc::from(self.finish_boxed()?))
+    }
+
+    fn check_complete(&self) -> Result<(), FinishError> {
+        let missing = if self.writer.all_returned() {
+            self.writer.taken..self.capacity
+        } else {
+            self.writer.missing_range()
+        };
+        if !missing.is_empty() {
+            return Err(FinishError { missing });
+        }
+        Ok(())
+    }
+
+    fn has_outstanding_shards(&self) -> bool {
+        self.writer.has_outstanding_shards()
+    }
+}
+
+impl<T> Drop for VecBuilder<T> {
+    fn drop(&mut self) {
+        let outstanding = self.has_outstanding_shards();
+        // Safety: The writer isn't used again.
+        unsafe { ManuallyDrop::drop(&mut self.writer) };
+        if !outstanding {
+            // Safety: The writer that borrowed the storage is gone and there are no outstanding
+            // shards.
+            drop(unsafe { Box::from_raw(self.storage) });
+        }
+    }
+}
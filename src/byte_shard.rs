@@ -0,0 +1,159 @@
+//! Helpers that only make sense when the output is a byte buffer, e.g. an object file.
+
+use crate::InsufficientCapacity;
+use crate::Shard;
+use std::io;
+use std::mem::MaybeUninit;
+
+impl<'vec> Shard<'vec, u8> {
+    /// Writes zero bytes until the output offset is a multiple of `alignment`. Panics if there is
+    /// insufficient capacity for the padding or if `alignment` is zero.
+    #[track_caller]
+    pub fn pad_to_alignment(&mut self, alignment: usize) {
+        self.try_pad_to_alignment(alignment).unwrap();
+    }
+
+    /// As for `pad_to_alignment`, but returns an error rather than panicking if there is
+    /// insufficient capacity. Nothing is written on failure.
+    pub fn try_pad_to_alignment(&mut self, alignment: usize) -> Result<(), InsufficientCapacity> {
+        let aligned = self.output_offset().next_multiple_of(alignment);
+        if aligned > self.end_offset {
+            return Err(InsufficientCapacity);
+        }
+        while self.initialised_up_to < aligned {
+            self.push(0);
+        }
+        Ok(())
+    }
+
+    /// Writes a record that must start at an offset that's a multiple of `alignment`. Zero padding
+    /// is inserted before the record as needed, then `write` is called to write the record's
+    /// contents. Returns the offset in the output vector at which the record starts.
+    #[track_caller]
+    pub fn write_record(&mut self, alignment: usize, write: impl FnOnce(&mut Self)) -> usize {
+        self.pad_to_alignment(alignment);
+        let record_offset = self.output_offset();
+        write(self);
+        record_offset
+    }
+
+    /// Writes `value` as an unsigned LEB128, as used by DWARF and WebAssembly. Panics if there is
+    /// insufficient capacity.
+    #[track_caller]
+    pub fn write_uleb128(&mut self, value: u64) {
+        self.try_write_uleb128(value).unwrap();
+    }
+
+    /// As for `write_uleb128`, but returns an error rather than panicking if there is insufficient
+    /// capacity. Nothing is written on failure.
+    pub fn try_write_uleb128(&mut self, mut value: u64) -> Result<(), InsufficientCapacity> {
+        self.check_remaining(uleb128_len(value))?;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.push(byte);
+                return Ok(());
+            }
+            self.push(byte | 0x80);
+        }
+    }
+
+    /// Writes `value` as a signed LEB128. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn write_sleb128(&mut self, value: i64) {
+        self.try_write_sleb128(value).unwrap();
+    }
+
+    /// As for `write_sleb128`, but returns an error rather than panicking if there is insufficient
+    /// capacity. Nothing is written on failure.
+    pub fn try_write_sleb128(&mut self, mut value: i64) -> Result<(), InsufficientCapacity> {
+        self.check_remaining(sleb128_len(value))?;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            // Arithmetic shift, so that negative values stay negative.
+            value >>= 7;
+            let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+            if done {
+                self.push(byte);
+                return Ok(());
+            }
+            self.push(byte | 0x80);
+        }
+    }
+
+    /// Reads from `reader` until either the shard is full or the reader reaches end-of-file.
+    /// Returns the number of bytes read. If the reader fails, then bytes read before the failure
+    /// remain in the shard.
+    pub fn read_from(&mut self, mut reader: impl io::Read) -> io::Result<usize> {
+        let spare = self.spare_capacity_mut();
+        // `Read` implementations are allowed to read from the buffer they're given, so it needs to
+        // be initialised.
+        spare.fill(MaybeUninit::new(0));
+        // Safety: We just initialised all of `spare`.
+        let buffer = unsafe { &mut *(spare as *mut [MaybeUninit<u8>] as *mut [u8]) };
+        let mut total = 0;
+        let result = loop {
+            if total == buffer.len() {
+                break Ok(total);
+            }
+            match reader.read(&mut buffer[total..]) {
+                Ok(0) => break Ok(total),
+                Ok(n) => total += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => break Err(error),
+            }
+        };
+        self.mark_written(total);
+        result
+    }
+
+    /// As for `read_from`, but fails with `ErrorKind::UnexpectedEof` if the reader reaches
+    /// end-of-file before the shard is full.
+    pub fn read_exact_from(&mut self, reader: impl io::Read) -> io::Result<()> {
+        self.read_from(reader)?;
+        if !self.is_full() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    fn check_remaining(&self, len: usize) -> Result<(), InsufficientCapacity> {
+        if self.remaining_capacity() < len {
+            return Err(InsufficientCapacity);
+        }
+        Ok(())
+    }
+}
+
+/// Writes as much of each buffer as will fit in the remaining capacity of the shard, so once the
+/// shard is full, writes return 0 and `write_all` fails with `ErrorKind::WriteZero`.
+impl io::Write for Shard<'_, u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining_capacity());
+        self.copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the number of bytes needed to encode `value` as an unsigned LEB128. Useful when
+/// planning shard sizes.
+pub fn uleb128_len(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
+/// Returns the number of bytes needed to encode `value` as a signed LEB128.
+pub fn sleb128_len(value: i64) -> usize {
+    let redundant_bits = if value < 0 {
+        value.leading_ones()
+    } else {
+        value.leading_zeros()
+    } as usize;
+    // One more bit than the magnitude is needed for the sign.
+    (65 - redundant_bits).div_ceil(7)
+}
@@ -0,0 +1,151 @@
+//! Checksums of the whole output, computed in parallel once all shards have been returned.
+
+use crate::parallel::available_threads;
+use crate::parallel::map_in_parallel;
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+
+/// The reversed polynomial used by CRC-32 (ISO-HDLC), as used by zlib, PNG, gzip etc.
+const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+
+/// Don't bother splitting checksum calculation between threads for less than this many bytes.
+const MIN_BYTES_PER_THREAD: usize = 64 * 1024;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A 4 byte region at the end of the output that will be filled with a little-endian CRC-32 of
+/// everything before it.
+pub struct Crc32Trailer<'vec> {
+    shard: Shard<'vec, u8>,
+}
+
+impl<'vec> VecWriter<'vec, u8> {
+    /// Takes the next 4 bytes of the vector as a trailer to hold a CRC-32 of all preceding bytes.
+    /// This will normally be the last thing taken. Panics if there is insufficient capacity.
+    pub fn take_crc32_trailer(&mut self) -> Crc32Trailer<'vec> {
+        Crc32Trailer {
+            shard: self.take_shard(4),
+        }
+    }
+
+    /// As for `take_crc32_trailer`, but returns None if there is insufficient capacity.
+    pub fn try_take_crc32_trailer(&mut self) -> Option<Crc32Trailer<'vec>> {
+        Some(Crc32Trailer {
+            shard: self.try_take_shard(4)?,
+        })
+    }
+
+    /// Computes a CRC-32 of everything in the vector before `trailer`, writes it into the trailer
+    /// and returns the trailer to the vector. All shards before the trailer must have already been
+    /// returned. The checksum is computed in parallel. Returns the checksum.
+    pub fn return_crc32_trailer(&mut self, trailer: Crc32Trailer) -> Result<u32, InitError> {
+        let mut shard = trailer.shard;
+        if !self.owns(&shard) {
+            return Err(shard.wrong_vec_error());
+        }
+        if self.storage.len() != shard.start_offset {
+            return Err(self.missing_shard_error());
+        }
+        let crc = parallel_crc32(self.storage);
+        for byte in crc.to_le_bytes() {
+            shard.push(byte);
+        }
+        self.try_return_shard(shard)?;
+        Ok(crc)
+    }
+}
+
+fn parallel_crc32(bytes: &[u8]) -> u32 {
+    let num_threads = available_threads();
+    let chunk_size = bytes.len().div_ceil(num_threads).max(MIN_BYTES_PER_THREAD);
+    if chunk_size >= bytes.len() {
+        return crc32(bytes);
+    }
+    let chunk_crcs = map_in_parallel(bytes.chunks(chunk_size).collect(), |chunk| {
+        (crc32(chunk), chunk.len())
+    });
+    chunk_crcs.into_iter().fold(0, |crc, (chunk_crc, len)| {
+        crc32_combine(crc, chunk_crc, len)
+    })
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Returns the CRC-32 of the concatenation of two byte sequences, given the CRC-32 of each and the
+/// length of the second. This is the algorithm from zlib's `crc32_combine`.
+fn crc32_combine(mut crc1: u32, crc2: u32, mut len2: usize) -> u32 {
+    fn times(matrix: &[u32; 32], mut vector: u32) -> u32 {
+        let mut sum = 0;
+        let mut row = 0;
+        while vector != 0 {
+            if vector & 1 != 0 {
+                sum ^= matrix[row];
+            }
+            vector >>= 1;
+            row += 1;
+        }
+        sum
+    }
+
+    fn square(matrix: &[u32; 32]) -> [u32; 32] {
+        std::array::from_fn(|n| times(matrix, matrix[n]))
+    }
+
+    if len2 == 0 {
+        return crc1;
+    }
+
+    // Operator for a single zero bit.
+    let mut odd = [0; 32];
+    odd[0] = CRC32_POLYNOMIAL;
+    for (n, row) in odd.iter_mut().enumerate().skip(1) {
+        *row = 1 << (n - 1);
+    }
+    // Operators for two and then four zero bits.
+    let mut even = square(&odd);
+    odd = square(&even);
+
+    // Apply operators for each set bit of `len2`, starting with one zero byte.
+    loop {
+        even = square(&odd);
+        if len2 & 1 != 0 {
+            crc1 = times(&even, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+        odd = square(&even);
+        if len2 & 1 != 0 {
+            crc1 = times(&odd, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+    }
+    crc1 ^ crc2
+}
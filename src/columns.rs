@@ -0,0 +1,199 @@
+//! Writing struct-of-arrays output, where each row has a value in each of several vecs.
+
+use crate::InitError;
+use crate::InsufficientCapacity;
+use crate::Shard;
+use crate::VecWriter;
+
+/// A row of a `MultiVecWriter`. Implemented for tuples of two to four element types, one per
+/// column.
+pub trait Row<'vec>: Sized {
+    /// A tuple of `&mut Vec`, one per column.
+    type Vecs;
+
+    /// A tuple of writers, one per column.
+    type Writers;
+
+    /// A tuple of shards, one per column.
+    type Shards;
+
+    /// Creates a writer for each column. Panics if the columns have different lengths.
+    fn new_writers(vecs: Self::Vecs) -> Self::Writers;
+
+    /// Returns the capacity that hasn't been taken from all columns.
+    fn remaining_capacity(writers: &Self::Writers) -> usize;
+
+    /// Takes `n` elements from each column. The caller must check that there's enough capacity.
+    fn take_shards(writers: &mut Self::Writers, n: usize) -> Self::Shards;
+
+    /// Returns the number of rows that can still be pushed to the shards.
+    fn shard_remaining_capacity(shards: &Self::Shards) -> usize;
+
+    /// Pushes each value of the row to the shard for its column. The caller must check that
+    /// there's enough capacity.
+    fn push(shards: &mut Self::Shards, row: Self);
+
+    /// Returns each shard to the writer for its column, or none of them if any can't be returned.
+    fn return_shards(writers: &mut Self::Writers, shards: Self::Shards) -> Result<(), InitError>;
+}
+
+/// Writes several vecs with identical lengths, e.g. keys, values and flags stored as separate
+/// columns. Each shard spans the same range of rows in every vec and values are pushed a whole row
+/// at a time, so the columns stay in lockstep. `R` is a tuple of the column element types.
+pub struct MultiVecWriter<'vec, R: Row<'vec>> {
+    writers: R::Writers,
+}
+
+/// A shard of each column of a `MultiVecWriter`, covering the same range of rows.
+pub struct MultiShard<'vec, R: Row<'vec>> {
+    shards: R::Shards,
+}
+
+impl<'vec, R: Row<'vec>> MultiVecWriter<'vec, R> {
+    /// Creates a writer from a tuple of `&mut Vec`, one per column. Panics if the columns don't
+    /// already have the same length, since otherwise each shard would cover different rows in
+    /// each column.
+    #[track_caller]
+    pub fn new(vecs: R::Vecs) -> Self {
+        Self {
+            writers: R::new_writers(vecs),
+        }
+    }
+
+    /// Returns the number of rows that can still be taken, which is limited by the column with the
+    /// least capacity.
+    pub fn remaining_capacity(&self) -> usize {
+        R::remaining_capacity(&self.writers)
+    }
+
+    /// Takes the next `n` rows or panics if there is insufficient capacity in any column.
+    #[track_caller]
+    pub fn take_shard(&mut self, n: usize) -> MultiShard<'vec, R> {
+        self.try_take_shard(n).unwrap_or_else(|| {
+            panic!(
+                "Tried to take {n} when only {} available",
+                self.remaining_capacity()
+            );
+        })
+    }
+
+    /// Takes the next `n` rows or returns None if there is insufficient capacity in any column.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<MultiShard<'vec, R>> {
+        if n > self.remaining_capacity() {
+            return None;
+        }
+        Some(MultiShard {
+            shards: R::take_shards(&mut self.writers, n),
+        })
+    }
+
+    /// Returns a shard to each column. The shard must have been fully initialised. Panics on
+    /// failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: MultiShard<'vec, R>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking. Either all
+    /// columns are returned or none of them are.
+    pub fn try_return_shard(&mut self, shard: MultiShard<'vec, R>) -> Result<(), InitError> {
+        R::return_shards(&mut self.writers, shard.shards)
+    }
+
+    /// Returns the underlying writers, one per column.
+    pub fn into_writers(self) -> R::Writers {
+        self.writers
+    }
+}
+
+impl<'vec, R: Row<'vec>> MultiShard<'vec, R> {
+    /// Appends a row, pushing each of its values to the corresponding column. Panics if the shard
+    /// is full.
+    #[track_caller]
+    pub fn push(&mut self, row: R) {
+        self.try_push(row).unwrap();
+    }
+
+    /// Appends a row or returns an error if the shard is full.
+    pub fn try_push(&mut self, row: R) -> Result<(), InsufficientCapacity> {
+        if self.remaining_capacity() == 0 {
+            return Err(InsufficientCapacity);
+        }
+        R::push(&mut self.shards, row);
+        Ok(())
+    }
+
+    /// Returns the number of rows that can still be pushed.
+    pub fn remaining_capacity(&self) -> usize {
+        R::shard_remaining_capacity(&self.shards)
+    }
+
+    /// Returns the shard for each column, e.g. to read back what has been written.
+    pub fn columns(&self) -> &R::Shards {
+        &self.shards
+    }
+}
+
+macro_rules! impl_row {
+    ($($column:ident: $index:tt),*) => {
+        impl<'vec, $($column: 'vec),*> Row<'vec> for ($($column,)*) {
+            type Vecs = ($(&'vec mut Vec<$column>,)*);
+            type Writers = ($(VecWriter<'vec, $column>,)*);
+            type Shards = ($(Shard<'vec, $column>,)*);
+
+            #[track_caller]
+            fn new_writers(vecs: Self::Vecs) -> Self::Writers {
+                let lengths = [$(vecs.$index.len()),*];
+                assert!(
+                    lengths.iter().all(|&len| len == lengths[0]),
+                    "Columns must have the same length, but have lengths {lengths:?}"
+                );
+                ($(VecWriter::new(vecs.$index),)*)
+            }
+
+            fn remaining_capacity(writers: &Self::Writers) -> usize {
+                [$(writers.$index.remaining_capacity()),*]
+                    .into_iter()
+                    .min()
+                    .unwrap_or(0)
+            }
+
+            fn take_shards(writers: &mut Self::Writers, n: usize) -> Self::Shards {
+                ($(writers.$index.take_shard(n),)*)
+            }
+
+            fn shard_remaining_capacity(shards: &Self::Shards) -> usize {
+                // The columns are pushed together, so they all have the same remaining capacity.
+                shards.0.remaining_capacity()
+            }
+
+            fn push(shards: &mut Self::Shards, row: Self) {
+                $(shards.$index.push(row.$index);)*
+            }
+
+            fn return_shards(
+                writers: &mut Self::Writers,
+                mut shards: Self::Shards,
+            ) -> Result<(), InitError> {
+                // Check everything up-front, so that once we start returning shards, nothing can
+                // fail. The shards were filled together, so if one is full, they all are.
+                $(
+                    if !writers.$index.owns(&shards.$index) {
+                        return Err(shards.$index.wrong_vec_error());
+                    }
+                )*
+                shards.0.check_returnable()?;
+                $(
+                    writers.$index
+                        .try_return_shard(shards.$index)
+                        .expect("Shard was validated");
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_row!(A: 0, B: 1);
+impl_row!(A: 0, B: 1, C: 2);
+impl_row!(A: 0, B: 1, C: 2, D: 3);
@@ -0,0 +1,135 @@
+//! Returning shards that weren't completely filled, then closing up the gaps they leave, for when
+//! workers only know an upper bound on how much they'll write.
+
+use crate::parallel::for_each_in_parallel;
+use crate::parallel::SendPtr;
+use crate::trace;
+use crate::InitError;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::ops::Range;
+
+impl<'vec, T: 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Returns a shard that may not have been completely filled, recording how much was written.
+    /// Shards can be returned in any order. The unwritten parts of underfilled shards are removed
+    /// by `finish_compacted`, which must be used to finish the writer once any shard has been
+    /// returned this way. Patches queued on the shard can't be applied, so are discarded. Panics on
+    /// failure.
+    #[track_caller]
+    pub fn return_underfilled_shard(&mut self, shard: Shard<T>) {
+        self.try_return_underfilled_shard(shard).unwrap()
+    }
+
+    /// As for `return_underfilled_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_underfilled_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        self.return_underfilled_shard_impl(shard)
+            .inspect_err(trace::return_failed)
+    }
+
+    fn return_underfilled_shard_impl(&mut self, mut shard: Shard<T>) -> Result<(), InitError> {
+        if !self.owns(&shard) {
+            return Err(shard.wrong_vec_error());
+        }
+        if shard.has_published_views() {
+            return Err(InitError::PrefixStillPublished {
+                range: shard.range(),
+            });
+        }
+        self.validate_return(&shard)?;
+        if let Some(processor) = self.return_processor.as_mut() {
+            processor(
+                shard.start_offset..shard.initialised_up_to,
+                shard.written_mut(),
+            );
+        }
+        if let (Some(progress), None) = (&self.progress, &shard.progress) {
+            progress.add_written(shard.initialised_up_to - shard.start_offset);
+        }
+        self.underfilled.insert(
+            shard.start_offset,
+            (shard.initialised_up_to, shard.end_offset),
+        );
+        trace::shard_returned(&shard.range());
+
+        // The values written into the shard are now owned by the writer until they're moved into
+        // place by `finish_compacted`.
+        core::mem::forget(shard);
+        Ok(())
+    }
+}
+
+impl<T: Send, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Checks that all shards that were taken have been returned, then moves the contents of
+    /// shards that follow underfilled shards to the left, so that the output has no gaps, and sets
+    /// the length of the vec to the total written. Regions that don't overlap are moved in
+    /// parallel. Panics if any views created by `return_and_freeze` still exist, since their
+    /// values would move.
+    #[track_caller]
+    pub fn finish_compacted(mut self) -> Result<(), InitError> {
+        if let Some(check) = self.finish_check.as_mut() {
+            check.finished();
+        }
+        if let Some(missing) = self.outstanding_ranges().next() {
+            return Err(InitError::OutOfOrder { missing });
+        }
+        assert!(
+            !self.has_frozen_views(),
+            "Can't compact while views of returned shards exist"
+        );
+
+        // The written part of each region that was returned beyond the length of the vec.
+        let mut written: Vec<Range<usize>> = std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(start, end)| start..end)
+            .chain(
+                std::mem::take(&mut self.underfilled)
+                    .into_iter()
+                    .map(|(start, (written_end, _))| start..written_end),
+            )
+            .collect();
+        written.sort_unstable_by_key(|range| range.start);
+
+        // Work out where each region needs to move to, then group the moves into waves, where no
+        // move in a wave overwrites the source of an earlier move in the same wave.
+        let mut waves: Vec<Vec<(Range<usize>, usize)>> = Vec::new();
+        let mut wave_source_end = usize::MAX;
+        let mut destination = self.storage.len();
+        for source in written {
+            let len = source.len();
+            if source.start != destination && len != 0 {
+                if destination < wave_source_end {
+                    waves.push(Vec::new());
+                }
+                wave_source_end = source.end;
+                waves.last_mut().unwrap().push((source, destination));
+            }
+            destination += len;
+        }
+
+        let base = SendPtr::new(self.storage.as_mut_ptr());
+        for wave in waves {
+            for_each_in_parallel(wave, |(source, destination)| {
+                // Safety: The source was written by returned shards and the destination is either
+                // unused capacity or values that have already been moved. Moves within a wave don't
+                // touch each other's sources. A region might overlap the place it moves to, which
+                // `copy` permits.
+                unsafe {
+                    std::ptr::copy(
+                        base.get().add(source.start),
+                        base.get().add(destination),
+                        source.len(),
+                    );
+                }
+            });
+        }
+
+        // Safety: Everything up to `destination` has now been written, either before the writer was
+        // created, by `push`, or moved into place above.
+        unsafe { self.storage.set_len(destination) };
+        self.taken = destination;
+        self.closed = true;
+        self.update_progress_total();
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::parallel::map_in_parallel;
+use crate::Frame;
+use crate::Shard;
+use crate::VecWriter;
+
+/// Splits `input` into chunks of `chunk_size` bytes (the last may be shorter) and compresses them
+/// in parallel into a single output buffer, returning the buffer together with a `Frame` describing
+/// each compressed chunk, suitable for passing to `decode_frames`. Each chunk is compressed into a
+/// shard of `bound(chunk_len)` bytes, where `bound` must be the worst-case compressed size for the
+/// codec. `compress` is called with each chunk and its shard and may leave the end of the shard
+/// unused. Once all chunks are compressed, the frames are compacted so that there are no gaps
+/// between them. Panics if `chunk_size` is zero.
+pub fn compress_frames(
+    input: &[u8],
+    chunk_size: usize,
+    bound: impl Fn(usize) -> usize,
+    compress: impl Fn(&[u8], &mut Shard<u8>) + Sync,
+) -> (Vec<u8>, Vec<Frame>) {
+    assert!(chunk_size > 0, "Chunk size must be non-zero");
+    let chunks: Vec<&[u8]> = input.chunks(chunk_size).collect();
+    let bounds: Vec<usize> = chunks.iter().map(|chunk| bound(chunk.len())).collect();
+    let mut output = Vec::with_capacity(bounds.iter().sum());
+    let mut writer = VecWriter::new(&mut output);
+    let work = chunks
+        .iter()
+        .zip(&bounds)
+        .map(|(&chunk, &bound)| (chunk, writer.take_shard(bound)))
+        .collect();
+    let shards = map_in_parallel(work, |(chunk, mut shard)| {
+        compress(chunk, &mut shard);
+        let compressed_len = shard.output_offset() - shard.start_offset;
+        // Pad out the unused part of the shard so that it can be returned. The padding is removed
+        // when we compact.
+        while shard.try_push(0).is_ok() {}
+        (compressed_len, shard)
+    });
+    let mut compressed_lens = Vec::with_capacity(shards.len());
+    for (compressed_len, shard) in shards {
+        compressed_lens.push(compressed_len);
+        writer.return_shard(shard);
+    }
+
+    let mut frames = Vec::with_capacity(chunks.len());
+    let mut read_offset = 0;
+    let mut write_offset = 0;
+    for ((chunk, bound), compressed_len) in chunks.iter().zip(bounds).zip(compressed_lens) {
+        output.copy_within(read_offset..read_offset + compressed_len, write_offset);
+        frames.push(Frame {
+            input: write_offset..write_offset + compressed_len,
+            num_elements: chunk.len(),
+        });
+        read_offset += bound;
+        write_offset += compressed_len;
+    }
+    output.truncate(write_offset);
+    (output, frames)
+}
@@ -0,0 +1,34 @@
+//! Combining buffers that workers filled independently, for when the size of each worker's output
+//! isn't known until it has finished.
+
+use crate::parallel::for_each_in_parallel;
+use crate::VecWriter;
+
+impl<T: Send> VecWriter<'_, T> {
+    /// Appends the contents of each of `parts`, in order, moving the values from each part into its
+    /// own shard of the output from a separate thread. This allows workers to first build their
+    /// output in local vecs, then have it moved into place once all the sizes are known. Reserves
+    /// more space if needed, so panics if any shards are outstanding.
+    #[track_caller]
+    pub fn append_parts(&mut self, parts: Vec<Vec<T>>) {
+        let sizes: Vec<usize> = parts.iter().map(Vec::len).collect();
+        let total: usize = sizes.iter().sum();
+        if total > self.remaining_capacity() {
+            self.reserve(total);
+        }
+        let shards = self.take_shards_by_sizes(&sizes);
+        let mut work: Vec<_> = parts.into_iter().zip(shards).collect();
+        for_each_in_parallel(work.iter_mut().collect(), |(part, shard)| {
+            shard.append(part)
+        });
+        self.return_shards(work.into_iter().map(|(_, shard)| shard));
+    }
+}
+
+/// Returns a `Vec` containing the contents of each of `parts`, in order. The values are moved into
+/// the output in parallel.
+pub fn par_concat<T: Send>(parts: Vec<Vec<T>>) -> Vec<T> {
+    let mut output = Vec::with_capacity(parts.iter().map(Vec::len).sum());
+    VecWriter::new(&mut output).append_parts(parts);
+    output
+}
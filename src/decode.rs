@@ -0,0 +1,46 @@
+use crate::parallel::map_in_parallel;
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::ops::Range;
+
+/// A self-contained chunk of encoded input that decodes to a known number of elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The byte range of the input that contains the frame.
+    pub input: Range<usize>,
+
+    /// The number of elements that the frame decodes to.
+    pub num_elements: usize,
+}
+
+/// Decodes `frames` of `input` in parallel, producing a `Vec` containing the decoded elements of
+/// each frame, in order. `decode` is called once per frame with the frame's bytes and a shard that
+/// it must fill with exactly the frame's elements. Panics if a frame's input range is out of
+/// bounds.
+pub fn decode_frames<T: Send>(
+    input: &[u8],
+    frames: &[Frame],
+    decode: impl Fn(&[u8], &mut Shard<T>) + Sync,
+) -> Result<Vec<T>, InitError> {
+    let total = frames.iter().map(|frame| frame.num_elements).sum();
+    let mut output = Vec::with_capacity(total);
+    let mut writer = VecWriter::new(&mut output);
+    let work = frames
+        .iter()
+        .map(|frame| {
+            (
+                &input[frame.input.clone()],
+                writer.take_shard(frame.num_elements),
+            )
+        })
+        .collect();
+    let shards = map_in_parallel(work, |(bytes, mut shard)| {
+        decode(bytes, &mut shard);
+        shard
+    });
+    for shard in shards {
+        writer.try_return_shard(shard)?;
+    }
+    Ok(output)
+}
@@ -0,0 +1,102 @@
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+/// Identifies a shard that was taken from a writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShardId {
+    /// The exclusive end offset of the shard. Once the vec's length reaches this, the shard has
+    /// been returned.
+    end_offset: usize,
+}
+
+/// Allows threads to wait until particular shards have been returned to a writer. Obtained from
+/// `VecWriter::return_watcher`.
+#[derive(Clone)]
+pub struct ReturnWatcher {
+    state: Arc<ReturnState>,
+}
+
+struct ReturnState {
+    progress: Mutex<Progress>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// The length of the vec, i.e. the end of the last returned shard.
+    returned_up_to: usize,
+
+    /// Whether the writer has been dropped, in which case no more shards will be returned.
+    writer_dropped: bool,
+}
+
+/// Held by the writer. Wakes waiting threads when dropped.
+pub(crate) struct ReturnNotifier {
+    state: Arc<ReturnState>,
+}
+
+impl<T> Shard<'_, T> {
+    /// Returns an identifier for this shard that can be used to wait until it's been returned.
+    pub fn id(&self) -> ShardId {
+        ShardId {
+            end_offset: self.end_offset,
+        }
+    }
+}
+
+impl<T, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Returns a handle that threads can use to wait until specific shards have been returned.
+    pub fn return_watcher(&mut self) -> ReturnWatcher {
+        let returned_up_to = self.storage.len();
+        let notifier = self.return_notifier.get_or_insert_with(|| ReturnNotifier {
+            state: Arc::new(ReturnState {
+                progress: Mutex::new(Progress {
+                    returned_up_to,
+                    writer_dropped: false,
+                }),
+                condvar: Condvar::new(),
+            }),
+        });
+        ReturnWatcher {
+            state: notifier.state.clone(),
+        }
+    }
+}
+
+impl ReturnWatcher {
+    /// Blocks until the shard identified by `id` has been returned to the writer. Returns false if
+    /// the writer was dropped before that happened.
+    pub fn await_returned(&self, id: ShardId) -> bool {
+        let progress = self
+            .state
+            .condvar
+            .wait_while(self.state.progress.lock().unwrap(), |progress| {
+                progress.returned_up_to < id.end_offset && !progress.writer_dropped
+            })
+            .unwrap();
+        progress.returned_up_to >= id.end_offset
+    }
+
+    /// Returns whether the shard identified by `id` has been returned, without blocking.
+    pub fn is_returned(&self, id: ShardId) -> bool {
+        self.state.progress.lock().unwrap().returned_up_to >= id.end_offset
+    }
+}
+
+impl ReturnNotifier {
+    pub(crate) fn notify_returned_up_to(&self, returned_up_to: usize) {
+        self.state.progress.lock().unwrap().returned_up_to = returned_up_to;
+        self.state.condvar.notify_all();
+    }
+}
+
+impl Drop for ReturnNotifier {
+    fn drop(&mut self) {
+        self.state.progress.lock().unwrap().writer_dropped = true;
+        self.state.condvar.notify_all();
+    }
+}
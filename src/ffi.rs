@@ -0,0 +1,301 @@
+//! A C API for filling a byte buffer. Writers and shards are exposed as opaque handles. The same
+//! checks are applied as for the Rust API, with failures reported via `SvwStatus`.
+//!
+//! A writer must not be used from multiple threads at once, but shards may each be filled from a
+//! different thread.
+
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::sync::Arc;
+
+/// Status codes returned by the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvwStatus {
+    Ok = 0,
+    InsufficientCapacity = 1,
+    UninitElements = 2,
+    WrongVec = 3,
+    /// Part of the buffer will never be written, e.g. because a shard covering it was freed
+    /// rather than returned. See `svw_writer_missing_range`.
+    OutOfOrder = 4,
+    /// Shards were taken that are still held by the caller.
+    OutstandingShards = 5,
+    NullPointer = 6,
+    PrefixStillPublished = 7,
+    ValidationFailed = 8,
+}
+
+/// An opaque handle to a writer that owns the buffer being written.
+pub struct SvwWriter {
+    writer: VecWriter<'static, u8>,
+
+    /// The buffer that `writer` borrows. Allocated with `Box::into_raw` and only freed once
+    /// `writer` has been dropped and no shards are outstanding.
+    storage: *mut Vec<u8>,
+
+    /// Cloned into each shard handle, so that we can tell whether any are still held, as opposed to
+    /// having been freed without being returned.
+    shard_token: Arc<()>,
+}
+
+/// An opaque handle to a shard of a writer's buffer.
+pub struct SvwShard {
+    shard: Shard<'static, u8>,
+    _token: Arc<()>,
+}
+
+impl From<InitError> for SvwStatus {
+    fn from(error: InitError) -> Self {
+        match error {
+            InitError::UninitElements { .. } => SvwStatus::UninitElements,
+            InitError::WrongVec { .. } => SvwStatus::WrongVec,
+            InitError::OutOfOrder { .. } => SvwStatus::OutOfOrder,
+            InitError::PrefixStillPublished { .. } => SvwStatus::PrefixStillPublished,
+            InitError::ValidationFailed { .. } => SvwStatus::ValidationFailed,
+        }
+    }
+}
+
+impl SvwWriter {
+    fn has_outstanding_shards(&self) -> bool {
+        self.writer.has_outstanding_shards()
+    }
+
+    /// Returns whether any shard handles taken from this writer haven't yet been returned or
+    /// freed.
+    fn has_live_shards(&self) -> bool {
+        Arc::strong_count(&self.shard_token) > 1
+    }
+}
+
+/// Creates a writer that owns a new buffer with the specified capacity. The writer must eventually
+/// be passed to either `svw_writer_finish` or `svw_writer_free`.
+#[no_mangle]
+pub extern "C" fn svw_writer_new(capacity: usize) -> *mut SvwWriter {
+    let storage = Box::into_raw(Box::new(Vec::with_capacity(capacity)));
+    // Safety: `storage` was just allocated and won't be freed until the writer is dropped.
+    let writer = VecWriter::new(unsafe { &mut *storage });
+    Box::into_raw(Box::new(SvwWriter {
+        writer,
+        storage,
+        shard_token: Arc::new(()),
+    }))
+}
+
+/// Takes the next `n` bytes of the writer's buffer. Returns null if there is insufficient
+/// capacity. The shard must eventually be passed to either `svw_writer_return_shard` or
+/// `svw_shard_free`.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer handle that isn't being used concurrently by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn svw_writer_take_shard(writer: *mut SvwWriter, n: usize) -> *mut SvwShard {
+    let Some(writer) = (unsafe { writer.as_mut() }) else {
+        return std::ptr::null_mut();
+    };
+    match writer.writer.try_take_shard(n) {
+        Some(shard) => Box::into_raw(Box::new(SvwShard {
+            shard,
+            _token: writer.shard_token.clone(),
+        })),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Appends `len` bytes from `data` to the shard. If there's insufficient capacity for all of them,
+/// nothing is written.
+///
+/// # Safety
+///
+/// `shard` must be a valid shard handle that isn't being used concurrently by another thread.
+/// `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn svw_shard_push_bytes(
+    shard: *mut SvwShard,
+    data: *const u8,
+    len: usize,
+) -> SvwStatus {
+    let Some(shard) = (unsafe { shard.as_mut() }) else {
+        return SvwStatus::NullPointer;
+    };
+    if data.is_null() && len != 0 {
+        return SvwStatus::NullPointer;
+    }
+    let shard = &mut shard.shard;
+    if shard.end_offset - shard.initialised_up_to < len {
+        return SvwStatus::InsufficientCapacity;
+    }
+    if len != 0 {
+        // Safety: The caller promised that `data` is valid for `len` bytes.
+        let data = unsafe { std::slice::from_raw_parts(data, len) };
+        for &byte in data {
+            shard.push(byte);
+        }
+    }
+    SvwStatus::Ok
+}
+
+/// Returns a shard to its writer. The shard handle is consumed regardless of whether this
+/// succeeds. See `VecWriter::return_shard` for requirements.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer handle and `shard` a valid shard handle. Neither may be in use
+/// by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn svw_writer_return_shard(
+    writer: *mut SvwWriter,
+    shard: *mut SvwShard,
+) -> SvwStatus {
+    if shard.is_null() {
+        return SvwStatus::NullPointer;
+    }
+    // Safety: The caller promised that `shard` is a valid handle, which we now take ownership of.
+    let shard = unsafe { Box::from_raw(shard) };
+    let Some(writer) = (unsafe { writer.as_mut() }) else {
+        return SvwStatus::NullPointer;
+    };
+    match writer.writer.try_return_shard(shard.shard) {
+        Ok(()) => SvwStatus::Ok,
+        Err(error) => error.into(),
+    }
+}
+
+/// Frees a shard without returning it to its writer. Any bytes written to the shard are
+/// discarded.
+///
+/// # Safety
+///
+/// `shard` must be null or a valid shard handle that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn svw_shard_free(shard: *mut SvwShard) {
+    if !shard.is_null() {
+        // Safety: The caller promised that `shard` is a valid handle.
+        drop(unsafe { Box::from_raw(shard) });
+    }
+}
+
+/// Checks that all shards that were taken have been returned, then frees the writer and hands
+/// ownership of its buffer to the caller via the out-parameters. The buffer must be freed with
+/// `svw_buffer_free`. Returns `OutstandingShards` if the caller still holds shards, or `OutOfOrder`
+/// if part of the buffer will never be written because a shard was freed or failed to be
+/// returned, in which case `svw_writer_missing_range` reports where. On failure, the writer
+/// remains valid.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer handle that isn't in use by another thread. The out-parameters
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn svw_writer_finish(
+    writer: *mut SvwWriter,
+    data: *mut *mut u8,
+    len: *mut usize,
+    capacity: *mut usize,
+) -> SvwStatus {
+    if writer.is_null() || data.is_null() || len.is_null() || capacity.is_null() {
+        return SvwStatus::NullPointer;
+    }
+    // Safety: The caller promised that `writer` is a valid handle.
+    let svw_writer = unsafe { &*writer };
+    if svw_writer.has_live_shards() {
+        return SvwStatus::OutstandingShards;
+    }
+    if svw_writer.has_outstanding_shards() {
+        return SvwStatus::OutOfOrder;
+    }
+    // Safety: As above. We now take ownership of the writer.
+    let SvwWriter {
+        writer, storage, ..
+    } = *unsafe { Box::from_raw(writer) };
+    drop(writer);
+    // Safety: The writer that borrowed the storage is gone and there are no outstanding shards.
+    let storage = unsafe { Box::from_raw(storage) };
+    let mut storage = std::mem::ManuallyDrop::new(*storage);
+    // Safety: The caller promised that the out-parameters are valid for writes.
+    unsafe {
+        *data = storage.as_mut_ptr();
+        *len = storage.len();
+        *capacity = storage.capacity();
+    }
+    SvwStatus::Ok
+}
+
+/// Reports the first range of the buffer that hasn't been written, via the out-parameters `start`
+/// and `end`. Returns `OutOfOrder` if there is such a range, which will be the case after a shard
+/// was freed rather than returned, otherwise `Ok`, in which case `start` and `end` are both set to
+/// the length of the buffer.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer handle that isn't in use by another thread. The out-parameters
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn svw_writer_missing_range(
+    writer: *const SvwWriter,
+    start: *mut usize,
+    end: *mut usize,
+) -> SvwStatus {
+    if start.is_null() || end.is_null() {
+        return SvwStatus::NullPointer;
+    }
+    // Safety: The caller promised that `writer` is a valid handle.
+    let Some(writer) = (unsafe { writer.as_ref() }) else {
+        return SvwStatus::NullPointer;
+    };
+    let (range, status) = match writer.writer.outstanding_ranges().next() {
+        Some(range) => (range, SvwStatus::OutOfOrder),
+        None => {
+            let len = writer.writer.storage.len();
+            (len..len, SvwStatus::Ok)
+        }
+    };
+    // Safety: The caller promised that the out-parameters are valid for writes.
+    unsafe {
+        *start = range.start;
+        *end = range.end;
+    }
+    status
+}
+
+/// Frees a writer and its buffer. If the writer has outstanding shards, the buffer is leaked so
+/// that those shards remain valid. This includes the case where shards were freed rather than
+/// returned, leaving part of the buffer that will never be written.
+///
+/// # Safety
+///
+/// `writer` must be null or a valid writer handle that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn svw_writer_free(writer: *mut SvwWriter) {
+    if writer.is_null() {
+        return;
+    }
+    // Safety: The caller promised that `writer` is a valid handle.
+    let writer = unsafe { Box::from_raw(writer) };
+    let outstanding = writer.has_outstanding_shards();
+    let SvwWriter {
+        writer, storage, ..
+    } = *writer;
+    // The writer borrows the storage, so must be dropped first.
+    drop(writer);
+    if !outstanding {
+        // Safety: The writer that borrowed the storage is gone and there are no outstanding
+        // shards that could be pointing into it.
+        drop(unsafe { Box::from_raw(storage) });
+    }
+}
+
+/// Frees a buffer obtained from `svw_writer_finish`.
+///
+/// # Safety
+///
+/// The arguments must be exactly those obtained from `svw_writer_finish` and the buffer must not
+/// already have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn svw_buffer_free(data: *mut u8, len: usize, capacity: usize) {
+    // Safety: The caller promised that these came from a Vec that we gave them.
+    drop(unsafe { Vec::from_raw_parts(data, len, capacity) });
+}
@@ -0,0 +1,249 @@
+use crate::CapacityMismatch;
+use crate::InsufficientCapacity;
+use crate::Shard;
+use std::mem::MaybeUninit;
+use std::task::Poll;
+
+impl<T> Shard<'_, T> {
+    /// Returns the part of the shard that hasn't been written yet, e.g. so that it can be filled
+    /// via a raw pointer by foreign code or SIMD kernels. Call `set_written` afterwards to record
+    /// how much was initialised.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        // Safety: This region is within the capacity of the vec and is only accessible via this
+        // shard. `MaybeUninit` doesn't require the values to be initialised.
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.storage.add(self.initialised_up_to).cast(),
+                self.remaining_capacity(),
+            )
+        }
+    }
+
+    /// Marks the first `n` elements of the spare capacity as written.
+    ///
+    /// # Safety
+    ///
+    /// The first `n` elements returned by `spare_capacity_mut` must have been initialised. `n` must
+    /// not exceed the length of the spare capacity.
+    pub unsafe fn set_written(&mut self, n: usize) {
+        debug_assert!(n <= self.remaining_capacity());
+        self.mark_written(n);
+    }
+
+    /// Copies all of `values` into the shard. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn copy_from_slice(&mut self, values: &[T])
+    where
+        T: Copy,
+    {
+        self.try_copy_from_slice(values).unwrap();
+    }
+
+    /// As for `copy_from_slice`, but returns an error rather than panicking if there is
+    /// insufficient capacity. Nothing is written on failure.
+    pub fn try_copy_from_slice(&mut self, values: &[T]) -> Result<(), InsufficientCapacity>
+    where
+        T: Copy,
+    {
+        if self.remaining_capacity() < values.len() {
+            return Err(InsufficientCapacity);
+        }
+        // Safety: We checked that the values fit within the shard, which doesn't alias anything
+        // else. `T: Copy`, so there's nothing to drop if we're interrupted.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                self.storage.add(self.initialised_up_to),
+                values.len(),
+            );
+        }
+        self.mark_written(values.len());
+        Ok(())
+    }
+
+    /// Moves all values out of `values` into the shard, leaving `values` empty, as for
+    /// `Vec::append`. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn append(&mut self, values: &mut Vec<T>) {
+        self.try_append(values).unwrap();
+    }
+
+    /// As for `append`, but returns an error rather than panicking if there is insufficient
+    /// capacity. Nothing is moved on failure.
+    pub fn try_append(&mut self, values: &mut Vec<T>) -> Result<(), InsufficientCapacity> {
+        if self.remaining_capacity() < values.len() {
+            return Err(InsufficientCapacity);
+        }
+        // Safety: We checked that the values fit within the shard, which doesn't alias anything
+        // else. Setting the length of `values` to zero transfers ownership of the values to us.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                self.storage.add(self.initialised_up_to),
+                values.len(),
+            );
+            let len = values.len();
+            values.set_len(0);
+            self.mark_written(len);
+        }
+        Ok(())
+    }
+
+    /// Clones all of `values` into the shard. Panics if there is insufficient capacity. If a clone
+    /// panics, the values cloned before it remain in the shard.
+    #[track_caller]
+    pub fn extend_from_slice(&mut self, values: &[T])
+    where
+        T: Clone,
+    {
+        self.try_extend_from_slice(values).unwrap();
+    }
+
+    /// As for `extend_from_slice`, but returns an error rather than panicking if there is
+    /// insufficient capacity. Nothing is written on failure.
+    pub fn try_extend_from_slice(&mut self, values: &[T]) -> Result<(), InsufficientCapacity>
+    where
+        T: Clone,
+    {
+        if self.remaining_capacity() < values.len() {
+            return Err(InsufficientCapacity);
+        }
+        let mut guard = CloneGuard {
+            shard: self,
+            written: 0,
+        };
+        for value in values {
+            let offset = guard.shard.initialised_up_to + guard.written;
+            // Safety: We checked that the values fit within the shard, which doesn't alias
+            // anything else.
+            unsafe { guard.shard.storage.add(offset).write(value.clone()) };
+            guard.written += 1;
+        }
+        Ok(())
+    }
+
+    /// Fills all remaining capacity of the shard with clones of `value`.
+    pub fn fill(&mut self, value: T)
+    where
+        T: Clone,
+    {
+        if self.initialised_up_to == self.end_offset {
+            return;
+        }
+        while self.initialised_up_to + 1 < self.end_offset {
+            // Safety: The loop condition checks that there's capacity.
+            unsafe { self.push_unchecked(value.clone()) };
+        }
+        // Safety: We checked above that the shard wasn't full.
+        unsafe { self.push_unchecked(value) };
+    }
+
+    /// Fills all remaining capacity of the shard with values produced by calling `f`.
+    pub fn fill_with(&mut self, mut f: impl FnMut() -> T) {
+        while self.initialised_up_to < self.end_offset {
+            // Safety: The loop condition checks that there's capacity.
+            unsafe { self.push_unchecked(f()) };
+        }
+    }
+
+    /// Fills all remaining capacity of the shard with values produced by calling `f` with the
+    /// offset in the output vector of each value.
+    pub fn fill_with_index(&mut self, mut f: impl FnMut(usize) -> T) {
+        while self.initialised_up_to < self.end_offset {
+            let value = f(self.initialised_up_to);
+            // Safety: The loop condition checks that there's capacity.
+            unsafe { self.push_unchecked(value) };
+        }
+    }
+
+    /// As for `fill_with_index`, but `f` can fail. Stops at the first error and returns it. Values
+    /// written before the error remain in the shard.
+    pub fn try_fill_with<E>(&mut self, mut f: impl FnMut(usize) -> Result<T, E>) -> Result<(), E> {
+        while self.initialised_up_to < self.end_offset {
+            let value = f(self.initialised_up_to)?;
+            // Safety: The loop condition checks that there's capacity.
+            unsafe { self.push_unchecked(value) };
+        }
+        Ok(())
+    }
+
+    /// Fills all remaining capacity of the shard with the values from `values`, which must yield
+    /// exactly as many values as there is remaining capacity. Nothing is written on failure.
+    pub fn fill_from_iter<I>(&mut self, values: I) -> Result<(), CapacityMismatch>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        if values.len() != self.remaining_capacity() {
+            return Err(CapacityMismatch {
+                expected: values.len(),
+                actual: self.remaining_capacity(),
+            });
+        }
+        for value in values {
+            self.push(value);
+        }
+        Ok(())
+    }
+
+    /// Pushes values produced by `f` until either the shard is full, in which case
+    /// `Poll::Ready(())` is returned, or `budget` values have been pushed, in which case
+    /// `Poll::Pending` is returned. This allows a large shard to be filled from an async context in
+    /// slices of work, yielding to the executor between calls.
+    pub fn fill_with_budget(&mut self, budget: usize, mut f: impl FnMut() -> T) -> Poll<()> {
+        for _ in 0..budget {
+            if self.initialised_up_to == self.end_offset {
+                return Poll::Ready(());
+            }
+            self.push(f());
+        }
+        if self.initialised_up_to == self.end_offset {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// As for `fill_with_budget`, but clones values from `values`, starting at `*position`, which
+    /// is advanced past the values that were pushed. Panics if `values` runs out before the shard
+    /// is full.
+    #[track_caller]
+    pub fn extend_from_slice_with_budget(
+        &mut self,
+        values: &[T],
+        position: &mut usize,
+        budget: usize,
+    ) -> Poll<()>
+    where
+        T: Clone,
+    {
+        let mut remaining = values[*position..].iter();
+        self.fill_with_budget(budget, || {
+            *position += 1;
+            remaining.next().expect("Ran out of values").clone()
+        })
+    }
+}
+
+/// Records values written by `try_extend_from_slice` as initialised, even if a clone panics, so
+/// that they're dropped with the shard rather than leaked.
+struct CloneGuard<'shard, 'vec, T> {
+    shard: &'shard mut Shard<'vec, T>,
+    written: usize,
+}
+
+impl<T> Drop for CloneGuard<'_, '_, T> {
+    fn drop(&mut self) {
+        self.shard.mark_written(self.written);
+    }
+}
+
+/// Pushes each value to the shard. Panics if there is insufficient capacity.
+impl<T> Extend<T> for Shard<'_, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.push(value);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::trace;
+use crate::ShardableStorage;
+use crate::VecWriter;
+
+/// Detects writers that are dropped without `finish` being called. Held by the writer once
+/// `VecWriter::require_finish` has been called.
+pub(crate) struct FinishCheck {
+    shards_taken: bool,
+    finished: bool,
+}
+
+impl<T, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Requests that the writer check that `finish` is called if any shards are taken. If the
+    /// writer is dropped after taking shards without `finish` having been called, then in debug
+    /// builds it panics and in release builds it emits an error via `tracing`, if that feature is
+    /// enabled. This catches bugs where shards were never returned and the vec silently ends up
+    /// shorter than intended.
+    pub fn require_finish(&mut self) {
+        self.finish_check.get_or_insert(FinishCheck {
+            shards_taken: false,
+            finished: false,
+        });
+    }
+}
+
+impl FinishCheck {
+    pub(crate) fn shard_taken(&mut self) {
+        self.shards_taken = true;
+    }
+
+    pub(crate) fn finished(&mut self) {
+        self.finished = true;
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.shards_taken = false;
+        self.finished = false;
+    }
+}
+
+impl Drop for FinishCheck {
+    fn drop(&mut self) {
+        if !self.shards_taken || self.finished || std::thread::panicking() {
+            return;
+        }
+        debug_assert!(
+            false,
+            "VecWriter dropped after taking shards without `finish` being called"
+        );
+        trace::unfinished_writer_dropped();
+    }
+}
@@ -0,0 +1,90 @@
+//! Read-only views of regions that have been returned to a writer.
+
+use crate::InitError;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// A read-only view of the values written by a shard that has been returned to a writer. Created by
+/// `VecWriter::return_and_freeze`. Can be cloned and sent to other threads, so that later stages
+/// can read earlier regions of the output, e.g. for back-references, while the rest is still being
+/// written. While any such view exists, the writer can't reserve more space, apply patches or be
+/// reset. If the writer is aborted, then the values are leaked rather than dropped.
+pub struct FrozenRegion<'vec, T> {
+    start: *const T,
+    range: Range<usize>,
+
+    /// Shared with the writer, so that it can tell when all views have been dropped.
+    token: Arc<()>,
+
+    _phantom: PhantomData<&'vec T>,
+}
+
+unsafe impl<T: Sync> Send for FrozenRegion<'_, T> {}
+unsafe impl<T: Sync> Sync for FrozenRegion<'_, T> {}
+
+impl<'vec, T, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Returns a shard to the writer, as for `return_shard`, then returns a view of the values
+    /// that it wrote. Panics on failure.
+    #[track_caller]
+    pub fn return_and_freeze(&mut self, shard: Shard<T>) -> FrozenRegion<'vec, T> {
+        self.try_return_and_freeze(shard).unwrap()
+    }
+
+    /// As for `return_and_freeze`, but returns an error on failure rather than panicking.
+    pub fn try_return_and_freeze(
+        &mut self,
+        shard: Shard<T>,
+    ) -> Result<FrozenRegion<'vec, T>, InitError> {
+        let range = shard.range();
+        // Safety: `range.start` is within the capacity of the vec.
+        let start = unsafe { shard.storage.add(range.start) };
+        self.try_return_shard(shard)?;
+        let token = self.frozen.get_or_insert_with(|| Arc::new(()));
+        Ok(FrozenRegion {
+            start,
+            range,
+            token: token.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns whether any views returned by `return_and_freeze` still exist.
+    pub(crate) fn has_frozen_views(&mut self) -> bool {
+        self.frozen
+            .as_mut()
+            .is_some_and(|token| Arc::get_mut(token).is_none())
+    }
+}
+
+impl<T> FrozenRegion<'_, T> {
+    /// Returns the range of the output vector covered by this view.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+impl<T> Clone for FrozenRegion<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start,
+            range: self.range.clone(),
+            token: self.token.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for FrozenRegion<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // Safety: These values were initialised when the shard was returned and the writer won't
+        // modify, move or drop them while we exist.
+        unsafe { std::slice::from_raw_parts(self.start, self.range.len()) }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::parallel::available_threads;
+use crate::parallel::for_each_in_parallel;
+use crate::parallel::map_in_parallel;
+use crate::parallel::SendPtr;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Display;
+
+/// Returns a `Vec` containing a clone of `src[i]` for each `i` in `indices`, in order. The output
+/// is split between the available threads, which gather concurrently. Panics if any index is out
+/// of bounds.
+pub fn par_gather<T, I>(src: &[T], indices: &[I]) -> Vec<T>
+where
+    T: Clone + Send + Sync,
+    I: Copy + Sync + TryInto<usize>,
+{
+    let mut output = Vec::with_capacity(indices.len());
+    let mut writer = VecWriter::new(&mut output);
+    let chunk_size = indices.len().div_ceil(available_threads()).max(1);
+    let work = indices
+        .chunks(chunk_size)
+        .map(|chunk| (chunk, writer.take_shard(chunk.len())))
+        .collect();
+    let shards = map_in_parallel(work, |(chunk, mut shard)| {
+        for &index in chunk {
+            let value = index
+                .try_into()
+                .ok()
+                .and_then(|index| src.get(index))
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Index at position {} is out of bounds for source of length {}",
+                        shard.output_offset(),
+                        src.len()
+                    )
+                });
+            shard.push(value.clone());
+        }
+        shard
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    output
+}
+
+/// Returns a `Vec` where element `perm[i]` is `f(&src[i])`. `perm` must be a permutation of
+/// `0..src.len()`, which is checked before anything is written. The source is split between the
+/// available threads, which write to their destinations concurrently.
+pub fn par_scatter<S, T>(
+    src: &[S],
+    perm: &[usize],
+    f: impl Fn(&S) -> T + Sync,
+) -> Result<Vec<T>, ScatterError>
+where
+    S: Sync,
+    T: Send,
+{
+    if src.len() != perm.len() {
+        return Err(ScatterError::LengthMismatch {
+            src_len: src.len(),
+            perm_len: perm.len(),
+        });
+    }
+    let mut seen = vec![false; perm.len()];
+    for (position, &destination) in perm.iter().enumerate() {
+        let Some(seen) = seen.get_mut(destination) else {
+            return Err(ScatterError::OutOfBounds {
+                position,
+                destination,
+            });
+        };
+        if *seen {
+            return Err(ScatterError::Duplicate {
+                position,
+                destination,
+            });
+        }
+        *seen = true;
+    }
+
+    let mut output: Vec<T> = Vec::with_capacity(src.len());
+    let base = SendPtr::new(output.as_mut_ptr());
+    let chunk_size = src.len().div_ceil(available_threads()).max(1);
+    let work = src
+        .chunks(chunk_size)
+        .zip(perm.chunks(chunk_size))
+        .collect();
+    for_each_in_parallel(work, |(src, perm)| {
+        for (value, &destination) in src.iter().zip(perm) {
+            // Safety: We checked above that `perm` is a permutation, so each destination is within
+            // the capacity of `output` and is written exactly once.
+            unsafe { base.get().add(destination).write(f(value)) };
+        }
+    });
+    // Safety: Since `perm` is a permutation, every element up to `src.len()` has been written. If
+    // `f` panicked, we won't get here and the written values will be leaked.
+    unsafe { output.set_len(src.len()) };
+    Ok(output)
+}
+
+/// An error that can occur when scattering.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScatterError {
+    /// The source and permutation have different lengths.
+    LengthMismatch { src_len: usize, perm_len: usize },
+
+    /// The permutation contained a destination that's out of bounds.
+    OutOfBounds { position: usize, destination: usize },
+
+    /// The permutation contained the same destination more than once.
+    Duplicate { position: usize, destination: usize },
+}
+impl Error for ScatterError {}
+impl Display for ScatterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScatterError::LengthMismatch { src_len, perm_len } => write!(
+                f,
+                "Source length {src_len} doesn't match permutation length {perm_len}"
+            ),
+            ScatterError::OutOfBounds {
+                position,
+                destination,
+            } => write!(
+                f,
+                "Destination {destination} at position {position} is out of bounds"
+            ),
+            ScatterError::Duplicate {
+                position,
+                destination,
+            } => write!(
+                f,
+                "Destination {destination} at position {position} was already used"
+            ),
+        }
+    }
+}
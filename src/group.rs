@@ -0,0 +1,126 @@
+use crate::InitError;
+use crate::MultiOutputWriter;
+use crate::Shard;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Display;
+
+/// Several related shards that are returned together, with either all of them being committed or
+/// none of them. For example, a shard of values together with the corresponding shard of another
+/// output that holds their offsets.
+pub struct ShardGroup<'vec, T> {
+    shards: Vec<Shard<'vec, T>>,
+    require_equal_lengths: bool,
+}
+
+impl<'vec, T> ShardGroup<'vec, T> {
+    /// Creates an empty group.
+    pub fn new() -> Self {
+        Self {
+            shards: Vec::new(),
+            require_equal_lengths: false,
+        }
+    }
+
+    /// Requires that all shards in the group have the same length when the group is returned.
+    pub fn require_equal_lengths(&mut self) {
+        self.require_equal_lengths = true;
+    }
+
+    /// Adds a shard to the group.
+    pub fn push(&mut self, shard: Shard<'vec, T>) {
+        self.shards.push(shard);
+    }
+}
+
+impl<T> Default for ShardGroup<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> VecWriter<'_, T> {
+    /// Returns all shards in `group`, or none of them if any can't be returned. Panics on failure.
+    #[track_caller]
+    pub fn return_group(&mut self, group: ShardGroup<T>) {
+        self.try_return_group(group).unwrap()
+    }
+
+    /// As for `return_group`, but returns an error on failure rather than panicking. On failure,
+    /// all shards in the group are dropped.
+    pub fn try_return_group(&mut self, group: ShardGroup<T>) -> Result<(), GroupReturnError> {
+        return_group_to(std::slice::from_mut(self), group)
+    }
+}
+
+impl<T> MultiOutputWriter<'_, T> {
+    /// Returns all shards in `group`, each to whichever output it was taken from, or none of them
+    /// if any can't be returned. Panics on failure.
+    #[track_caller]
+    pub fn return_group(&mut self, group: ShardGroup<T>) {
+        self.try_return_group(group).unwrap()
+    }
+
+    /// As for `return_group`, but returns an error on failure rather than panicking. On failure,
+    /// all shards in the group are dropped.
+    pub fn try_return_group(&mut self, group: ShardGroup<T>) -> Result<(), GroupReturnError> {
+        return_group_to(&mut self.writers, group)
+    }
+}
+
+fn return_group_to<T>(
+    writers: &mut [VecWriter<T>],
+    mut group: ShardGroup<T>,
+) -> Result<(), GroupReturnError> {
+    if group.require_equal_lengths {
+        let mut lengths = group
+            .shards
+            .iter()
+            .map(|shard| shard.end_offset - shard.start_offset);
+        if let Some(first) = lengths.next() {
+            if lengths.any(|len| len != first) {
+                return Err(GroupReturnError::LengthMismatch);
+            }
+        }
+    }
+
+    // Check everything that `try_return_shard` would check, so that once we start returning shards,
+    // nothing can fail.
+    let mut destinations = Vec::with_capacity(group.shards.len());
+    for (index, shard) in group.shards.iter_mut().enumerate() {
+        let shard_error = |error| GroupReturnError::Shard { index, error };
+        let destination = writers
+            .iter()
+            .position(|writer| writer.owns(shard))
+            .ok_or_else(|| shard_error(shard.wrong_vec_error()))?;
+        shard.check_returnable().map_err(shard_error)?;
+        writers[destination]
+            .validate_return(shard)
+            .map_err(shard_error)?;
+        destinations.push(destination);
+    }
+
+    for (shard, destination) in group.shards.drain(..).zip(destinations) {
+        writers[destination].accept_shard(shard);
+    }
+    Ok(())
+}
+
+/// An error that can occur when returning a group of shards.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GroupReturnError {
+    /// The shard at `index` within the group couldn't be returned.
+    Shard { index: usize, error: InitError },
+
+    /// The group required shards of equal length, but they weren't.
+    LengthMismatch,
+}
+impl Error for GroupReturnError {}
+impl Display for GroupReturnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupReturnError::Shard { index, error } => write!(f, "{error} (shard {index})"),
+            GroupReturnError::LengthMismatch => write!(f, "Shards in group have different lengths"),
+        }
+    }
+}
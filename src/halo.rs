@@ -0,0 +1,51 @@
+use crate::Shard;
+use std::ops::Range;
+
+/// A read-only view of the part of an input slice that corresponds to a shard, extended by a few
+/// elements on either side (a halo or ghost cells). Useful for stencil-style computations where
+/// each output element depends on nearby input elements.
+#[derive(Debug, Clone, Copy)]
+pub struct HaloView<'input, U> {
+    data: &'input [U],
+
+    /// The index in the input of the first element of `data`.
+    start: usize,
+}
+
+impl<T> Shard<'_, T> {
+    /// Returns a view of the elements of `input` that have the same indices as this shard has in
+    /// the output vector, plus up to `halo` elements on either side. The view is truncated if it
+    /// would extend beyond the start or end of `input`.
+    pub fn input_with_halo<'input, U>(
+        &self,
+        input: &'input [U],
+        halo: usize,
+    ) -> HaloView<'input, U> {
+        let start = self.start_offset.saturating_sub(halo).min(input.len());
+        let end = self
+            .end_offset
+            .saturating_add(halo)
+            .clamp(start, input.len());
+        HaloView {
+            data: &input[start..end],
+            start,
+        }
+    }
+}
+
+impl<'input, U> HaloView<'input, U> {
+    /// Returns the element of the input at `index`, or None if `index` is outside the view.
+    pub fn get(&self, index: usize) -> Option<&'input U> {
+        self.data.get(index.checked_sub(self.start)?)
+    }
+
+    /// Returns the elements of the input covered by the view.
+    pub fn as_slice(&self) -> &'input [U] {
+        self.data
+    }
+
+    /// Returns the range of input indices covered by the view.
+    pub fn input_range(&self) -> Range<usize> {
+        self.start..self.start + self.data.len()
+    }
+}
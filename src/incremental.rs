@@ -0,0 +1,56 @@
+use crate::Shard;
+use crate::VecWriter;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Builds a vec made up of shards of known sizes, a few shards at a time, e.g. once per frame of a
+/// game or UI, so that building a large vec doesn't block for too long. Each shard is taken, filled
+/// and returned within a single call to `step`, so no shards are outstanding between calls.
+pub struct IncrementalBuild<'vec, T, F> {
+    writer: VecWriter<'vec, T>,
+    sizes: Vec<usize>,
+
+    /// The index of the next shard to be filled.
+    next: usize,
+
+    fill: F,
+}
+
+impl<'vec, T, F: FnMut(usize, &mut Shard<'vec, T>)> IncrementalBuild<'vec, T, F> {
+    /// Creates a build that will write shards of the specified sizes into `writer`. `fill` will be
+    /// called with the index of each shard and the shard, which it must fill completely.
+    pub fn new(writer: VecWriter<'vec, T>, sizes: Vec<usize>, fill: F) -> Self {
+        Self {
+            writer,
+            sizes,
+            next: 0,
+            fill,
+        }
+    }
+
+    /// Fills shards until either all shards have been filled, in which case `Poll::Ready` is
+    /// returned, or `budget` has elapsed, in which case `Poll::Pending` is returned. At least one
+    /// shard is filled per call, so progress is always made. The time taken by a single shard
+    /// isn't limited, so shards should be small relative to the budget. Panics if there is
+    /// insufficient capacity or if `fill` doesn't fill a shard.
+    #[track_caller]
+    pub fn step(&mut self, budget: Duration) -> Poll<()> {
+        let start = Instant::now();
+        while let Some(&size) = self.sizes.get(self.next) {
+            let mut shard = self.writer.take_shard(size);
+            (self.fill)(self.next, &mut shard);
+            self.writer.return_shard(shard);
+            self.next += 1;
+            if self.next < self.sizes.len() && start.elapsed() >= budget {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Returns the underlying writer, e.g. to call `finish` once all steps are done.
+    pub fn into_writer(self) -> VecWriter<'vec, T> {
+        self.writer
+    }
+}
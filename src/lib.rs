@@ -1,14 +1,242 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt::Debug;
 use std::fmt::Display;
 use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ops::Index;
+use std::ops::IndexMut;
+use std::ops::Range;
+use std::slice::SliceIndex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Weak;
+
+mod abort;
+mod array_shard;
+#[cfg(feature = "async")]
+mod async_return;
+mod atomic;
+mod back;
+mod boundaries;
+mod builder;
+mod byte_shard;
+mod checksum;
+mod columns;
+mod compact;
+mod compress;
+mod concat;
+mod decode;
+mod dependency;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fill;
+mod finish_check;
+mod frozen;
+mod gather;
+mod group;
+mod halo;
+mod incremental;
+#[cfg(feature = "memmap2")]
+mod mmap;
+mod morton;
+mod multi_output;
+mod nested;
+mod panic;
+mod parallel;
+mod patch;
+mod plan;
+mod progress;
+mod publish;
+mod queue;
+mod raw_parts;
+mod repeat;
+mod returner;
+mod scoped;
+mod scratch;
+mod section;
+mod seekable;
+mod shared;
+mod slots;
+mod sort;
+mod sparse;
+mod steal;
+mod storage;
+#[cfg(feature = "non_temporal")]
+mod streaming;
+mod string_table;
+mod string_writer;
+mod summary;
+mod tagged;
+pub mod testing;
+mod tile;
+mod trace;
+mod tree_hash;
+mod utf8;
+
+pub use abort::AbortSignal;
+pub use array_shard::ArrayShard;
+#[cfg(feature = "async")]
+pub use async_return::async_return_channel;
+#[cfg(feature = "async")]
+pub use async_return::AsyncShardCollector;
+#[cfg(feature = "async")]
+pub use async_return::AsyncShardReturner;
+pub use atomic::par_atomic_vec;
+pub use atomic::AtomicInteger;
+pub use boundaries::Boundaries;
+pub use boundaries::FinishError;
+pub use builder::VecBuilder;
+pub use byte_shard::sleb128_len;
+pub use byte_shard::uleb128_len;
+pub use checksum::Crc32Trailer;
+pub use columns::MultiShard;
+pub use columns::MultiVecWriter;
+pub use columns::Row;
+pub use compress::compress_frames;
+pub use concat::par_concat;
+pub use decode::decode_frames;
+pub use decode::Frame;
+use dependency::ReturnNotifier;
+pub use dependency::ReturnWatcher;
+pub use dependency::ShardId;
+use finish_check::FinishCheck;
+pub use frozen::FrozenRegion;
+pub use gather::par_gather;
+pub use gather::par_scatter;
+pub use gather::ScatterError;
+pub use group::GroupReturnError;
+pub use group::ShardGroup;
+pub use halo::HaloView;
+pub use incremental::IncrementalBuild;
+#[cfg(feature = "memmap2")]
+pub use mmap::MmapStorage;
+pub use morton::morton_coords;
+pub use morton::morton_index;
+pub use morton::MortonBlock;
+pub use morton::MortonWriter;
+pub use multi_output::IncompleteOutputError;
+pub use multi_output::MultiOutputWriter;
+pub use multi_output::OutputId;
+pub use nested::SubWriter;
+pub use panic::WorkerPanicked;
+use patch::Patch;
+pub use patch::PatchError;
+pub use progress::ProgressHandle;
+pub use publish::PublishedPrefix;
+pub use queue::ShardQueue;
+pub use repeat::par_repeat;
+pub use returner::return_channel;
+pub use returner::ShardCollector;
+pub use returner::ShardReturner;
+pub use scratch::Scratch;
+pub use scratch::ScratchPool;
+pub use section::BaseAddress;
+pub use section::Section;
+pub use seekable::SeekableBytes;
+pub use shared::SharedVecWriter;
+pub use slots::Slot;
+pub use slots::SlotSet;
+pub use sort::par_sort_into;
+pub use sparse::SparseShard;
+pub use steal::StealRequest;
+pub use storage::ShardableStorage;
+pub use storage::UninitStorage;
+pub use string_table::par_string_table;
+pub use string_table::StringTable;
+pub use string_writer::ShardedStringWriter;
+pub use string_writer::StringShard;
+pub use summary::ShardSummary;
+pub use summary::SummaryShard;
+pub use summary::SummaryWriter;
+pub use tagged::TaggedInitError;
+pub use tagged::TaggedShard;
+pub use tile::Rect;
+pub use tile::Tile;
+pub use tile::TileWriter;
+pub use tree_hash::tree_hash;
+pub use tree_hash::HashingWriter;
+pub use tree_hash::TreeHasher;
+pub use utf8::par_string_from_utf8;
 
 /// Builds a `Vec<T>`, with each variable-sized chunk of the Vec being initialised separately, most
-/// likely from a separate thread.
-pub struct VecWriter<'vec, T> {
-    storage: &'vec mut Vec<T>,
+/// likely from a separate thread. Other kinds of storage can be used instead of a `Vec` by
+/// implementing `ShardableStorage`, although many of the helpers in this crate are only available
+/// for `Vec`.
+///
+/// Zero-sized types are supported, with shards just counting the values written. Since a `Vec` of
+/// a zero-sized type has a capacity of `usize::MAX`, take shards of specific sizes rather than
+/// taking all remaining capacity, or finish with `return_partial_shard`.
+pub struct VecWriter<'vec, T, S = Vec<T>> {
+    storage: &'vec mut S,
     taken: usize,
+
+    /// The number of elements taken from the end of the capacity by `take_shard_back`. Reset to
+    /// zero once the regions taken from the front and the back meet.
+    back_taken: usize,
+
+    /// Patches from shards that have been returned, but which haven't yet been applied.
+    patches: Vec<Patch<T>>,
+
+    /// Called with the contents of each shard as it's returned.
+    return_processor: Option<ReturnProcessor<T>>,
+
+    /// Checks the contents of each shard as it's returned.
+    return_validator: Option<ReturnValidator<T>>,
+
+    /// The range covered by each shard that has been returned, in the order they were returned.
+    boundaries: Vec<Range<usize>>,
+
+    /// Shards that have been returned, but which are beyond the initialised length of the vec,
+    /// since an earlier shard hasn't yet been returned. Maps start offset to end offset.
+    pending: BTreeMap<usize, usize>,
+
+    /// Shards returned by `return_underfilled_shard`. Maps start offset to the end of what was
+    /// written and the end of the shard.
+    underfilled: BTreeMap<usize, (usize, usize)>,
+
+    /// Wakes threads waiting for shards to be returned, if any watchers have been created.
+    return_notifier: Option<ReturnNotifier>,
+
+    /// Present if the writer should check that `finish` is called.
+    finish_check: Option<FinishCheck>,
+
+    /// Set once a partial shard has been returned, after which nothing more can be taken.
+    closed: bool,
+
+    /// Identifies shards taken from this writer, so that shards can't be returned to the wrong
+    /// writer, even if the writers share storage.
+    id: usize,
+
+    /// The length of the vec when the writer was created, which `abort` restores.
+    original_len: usize,
+
+    /// Present if any workers have asked to be told about `abort`.
+    abort_signal: Option<AbortSignal>,
+
+    /// Present if anything has asked to monitor progress. Passed to each shard that's taken.
+    progress: Option<ProgressHandle>,
+
+    /// Shared with views created by `return_and_freeze`, if any.
+    frozen: Option<Arc<()>>,
+}
+
+static NEXT_WRITER_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_writer_id() -> usize {
+    NEXT_WRITER_ID.fetch_add(1, Ordering::Relaxed)
 }
 
+/// The size of a cache line on most current CPUs.
+const CACHE_LINE_SIZE: usize = 64;
+
+type ReturnProcessor<T> = Box<dyn FnMut(Range<usize>, &mut [T]) + Send>;
+
+type ReturnValidator<T> = Box<dyn FnMut(Range<usize>, &[T]) -> Result<(), String> + Send>;
+
 /// A mutable borrow of part of a `Vec`. Can be used to initialise that part of the `Vec` before
 /// returning it. Dropping a shard without returning it to the writer will drop any values that were
 /// written into it.
@@ -16,6 +244,9 @@ pub struct Shard<'vec, T> {
     /// Pointer to the start off `storage` on the builder.
     storage: *mut T,
 
+    /// The `id` of the writer that this shard was taken from.
+    writer_id: usize,
+
     /// The start offset within the original builder that we're responsible for.
     start_offset: usize,
 
@@ -25,13 +256,31 @@ pub struct Shard<'vec, T> {
     /// The exclusive offset up to which we have initialised.
     initialised_up_to: usize,
 
+    /// Writes to other parts of the output that have been deferred until all shards are returned.
+    patches: Vec<Patch<T>>,
+
+    /// Refers to the token held by views created by `publish_prefix`, if any. We only hold a weak
+    /// reference, so that shards split from this one can share it without keeping each other
+    /// from being returned.
+    published: Option<Weak<()>>,
+
+    /// Updated as values are written, if the writer's progress is being monitored.
+    progress: Option<ProgressHandle>,
+
     _phantom: PhantomData<&'vec mut T>,
 }
 
 impl<'vec, T> Drop for Shard<'vec, T> {
     fn drop(&mut self) {
         // We've been dropped without being returned to the writer, clean up any values that were
-        // written so that they don't leak.
+        // written so that they don't leak. If there are views of values that were published, then
+        // we can't drop any values, since we don't know how much was published.
+        if self.has_published_views() {
+            return;
+        }
+        if let Some(progress) = &self.progress {
+            progress.remove_written(self.initialised_up_to - self.start_offset);
+        }
         for offset in self.start_offset..self.initialised_up_to {
             unsafe { self.storage.add(offset).read() };
         }
@@ -41,11 +290,174 @@ impl<'vec, T> Drop for Shard<'vec, T> {
 unsafe impl<T: Send> Send for Shard<'_, T> {}
 unsafe impl<T: Sync> Sync for Shard<'_, T> {}
 
-impl<'vec, T> VecWriter<'vec, T> {
-    /// Creates a new writer that will write into the supplied `Vec`.
-    pub fn new(storage: &'vec mut Vec<T>) -> Self {
+impl<'vec, T: 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Creates a new writer that will write into the supplied `Vec` or other storage.
+    pub fn new(storage: &'vec mut S) -> Self {
         let taken = storage.len();
-        Self { storage, taken }
+        Self {
+            storage,
+            taken,
+            back_taken: 0,
+            patches: Vec::new(),
+            return_processor: None,
+            return_validator: None,
+            boundaries: Vec::new(),
+            pending: BTreeMap::new(),
+            underfilled: BTreeMap::new(),
+            return_notifier: None,
+            finish_check: None,
+            closed: false,
+            id: next_writer_id(),
+            original_len: taken,
+            abort_signal: None,
+            progress: None,
+            frozen: None,
+        }
+    }
+
+    /// Registers a function that will be called with the contents of each shard when it's
+    /// returned, before the shard's values become part of the vec. This can be used to transform
+    /// each region in-place, e.g. to encrypt or byte-swap it, or to inspect it, e.g. to compute a
+    /// checksum. The function runs on whichever thread returns the shard. It's passed the range of
+    /// the output vector covered by the shard. Replaces any previously registered function.
+    pub fn set_return_processor(
+        &mut self,
+        processor: impl FnMut(Range<usize>, &mut [T]) + Send + 'static,
+    ) {
+        self.return_processor = Some(Box::new(processor));
+    }
+
+    /// Registers a function that will be called with the contents of each shard when it's
+    /// returned, before any return processor. If it returns an error, the return fails with
+    /// `InitError::ValidationFailed` and the shard is dropped. This can be used to enforce
+    /// invariants, e.g. that each shard is sorted, rather than trusting every worker. It's passed
+    /// the range of the output vector covered by the values. Replaces any previously registered
+    /// function.
+    pub fn set_return_validator<E: Display>(
+        &mut self,
+        mut validator: impl FnMut(Range<usize>, &[T]) -> Result<(), E> + Send + 'static,
+    ) {
+        self.return_validator = Some(Box::new(move |range, values| {
+            validator(range, values).map_err(|error| error.to_string())
+        }));
+    }
+
+    /// Runs the return validator, if any, on the values written to `shard`.
+    pub(crate) fn validate_return(&mut self, shard: &Shard<T>) -> Result<(), InitError> {
+        let Some(validator) = self.return_validator.as_mut() else {
+            return Ok(());
+        };
+        validator(
+            shard.start_offset..shard.initialised_up_to,
+            shard.as_slice(),
+        )
+        .map_err(|message| InitError::ValidationFailed {
+            range: shard.range(),
+            message,
+        })
+    }
+
+    /// Returns whether `shard` was taken from this writer.
+    pub(crate) fn owns(&self, shard: &Shard<T>) -> bool {
+        shard.writer_id == self.id
+    }
+
+    /// Returns the first range of the output that was taken, but hasn't been returned, or if
+    /// everything taken has been returned, the capacity between the front and the back that
+    /// hasn't been taken.
+    pub(crate) fn missing_range(&self) -> Range<usize> {
+        let start = self.storage.len();
+        if start == self.taken && self.back_taken != 0 {
+            return self
+                .outstanding_ranges()
+                .next()
+                .unwrap_or(start..self.back_start());
+        }
+        let end = self
+            .pending
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.taken)
+            .min(self.taken);
+        start..end
+    }
+
+    /// Returns whether every shard that was taken has been returned and become part of the vec.
+    pub(crate) fn all_returned(&self) -> bool {
+        self.storage.len() == self.taken && self.back_taken == 0
+    }
+
+    /// Returns the ranges of the output that have been taken, but not yet returned, in order, e.g.
+    /// to find out which shard never came back when a build fails. Adjacent outstanding shards are
+    /// reported as a single range.
+    pub fn outstanding_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let mut returned: Vec<Range<usize>> = self
+            .pending
+            .iter()
+            .map(|(&start, &end)| start..end)
+            .chain(
+                self.underfilled
+                    .iter()
+                    .map(|(&start, &(_, end))| start..end),
+            )
+            .collect();
+        returned.sort_unstable_by_key(|range| range.start);
+        let front = self.storage.len()..self.taken;
+        let back = (self.back_taken != 0).then(|| self.back_start()..self.storage.capacity());
+        let mut outstanding = Vec::new();
+        for region in std::iter::once(front).chain(back) {
+            // Each outstanding range starts at the end of a returned region and ends at the start
+            // of the next.
+            let mut start = region.start;
+            for returned in returned.iter().filter(|r| region.contains(&r.start)) {
+                outstanding.push(start..returned.start);
+                start = returned.end;
+            }
+            outstanding.push(start..region.end);
+        }
+        outstanding.retain(|range| !range.is_empty());
+        outstanding.into_iter()
+    }
+
+    /// Returns whether any shards have been taken, but not yet returned.
+    pub fn has_outstanding_shards(&self) -> bool {
+        self.outstanding_ranges().next().is_some()
+    }
+
+    pub(crate) fn missing_shard_error(&self) -> InitError {
+        InitError::OutOfOrder {
+            missing: self.missing_range(),
+        }
+    }
+
+    /// Returns the number of elements that have been taken so far, including any that were in the
+    /// vec before the writer was created.
+    pub fn taken(&self) -> usize {
+        self.taken
+    }
+
+    /// Returns the number of elements of capacity that haven't yet been taken. This is zero once a
+    /// partial shard has been returned.
+    pub fn remaining_capacity(&self) -> usize {
+        if self.closed {
+            return 0;
+        }
+        self.back_start() - self.taken
+    }
+
+    /// Checks that the capacity not yet taken by shards is exactly `expected_total`. Useful for
+    /// catching planning bugs, e.g. where `reserve` was used and over-allocated, so that the sizes
+    /// of the planned shards don't add up to the capacity.
+    pub fn ensure_exact_capacity(&self, expected_total: usize) -> Result<(), CapacityMismatch> {
+        let actual = self.remaining_capacity();
+        if actual != expected_total {
+            return Err(CapacityMismatch {
+                expected: expected_total,
+                actual,
+            });
+        }
+        Ok(())
     }
 
     /// Takes the next `n` elements of the vector or panics if there is insufficient capacity.
@@ -53,31 +465,229 @@ impl<'vec, T> VecWriter<'vec, T> {
         self.try_take_shard(n).unwrap_or_else(|| {
             panic!(
                 "Tried to take {n} when only {} available",
-                self.storage.capacity() - self.taken
+                self.remaining_capacity()
             );
         })
     }
 
     /// Takes the next `n` elements of the vector or returns None if there is insufficient capacity.
     pub fn try_take_shard(&mut self, n: usize) -> Option<Shard<'vec, T>> {
-        let end_offset = self.taken.saturating_add(n);
-        if end_offset > self.storage.capacity() {
+        if n > self.remaining_capacity() {
             return None;
         }
+        let end_offset = self.taken + n;
         let shard = Shard {
             storage: self.storage.as_mut_ptr(),
+            writer_id: self.id,
             start_offset: self.taken,
             initialised_up_to: self.taken,
             end_offset,
+            patches: Vec::new(),
+            published: None,
+            progress: self.progress.clone(),
             _phantom: Default::default(),
         };
         self.taken = end_offset;
+        if let Some(check) = self.finish_check.as_mut() {
+            check.shard_taken();
+        }
+        trace::shard_taken(&shard.range());
+        self.join_back_if_met();
         Some(shard)
     }
 
-    /// Returns a shard to the vector, increasing the initialised length of the vector by the size
-    /// of the shard. The shard must have been fully initialised before being returned. Shards must
-    /// be returned in order. Panics on failure.
+    /// Appends a value directly to the vector without creating a shard, e.g. for a small header
+    /// between parallel regions. Panics if there is insufficient capacity or if any shards are
+    /// outstanding.
+    #[track_caller]
+    pub fn push(&mut self, value: T) {
+        self.try_push(value).unwrap();
+    }
+
+    /// As for `push`, but returns an error if there is insufficient capacity. Still panics if any
+    /// shards are outstanding, since that's a bug rather than something to recover from.
+    #[track_caller]
+    pub fn try_push(&mut self, value: T) -> Result<(), InsufficientCapacity> {
+        assert_eq!(
+            self.storage.len(),
+            self.taken,
+            "Can't push while shards are outstanding"
+        );
+        if self.remaining_capacity() == 0 {
+            return Err(InsufficientCapacity);
+        }
+        // Safety: We checked that there's spare capacity.
+        unsafe { self.storage.as_mut_ptr().add(self.taken).write(value) };
+        self.taken += 1;
+        // Safety: We just initialised the element at the previous length.
+        unsafe { self.storage.set_len(self.taken) };
+        if let Some(progress) = &self.progress {
+            progress.add_written(1);
+        }
+        self.join_back_if_met();
+        Ok(())
+    }
+
+    /// Appends each value from `values` directly to the vector. Panics if there is insufficient
+    /// capacity or if any shards are outstanding.
+    #[track_caller]
+    pub fn extend(&mut self, values: impl IntoIterator<Item = T>) {
+        for value in values {
+            self.push(value);
+        }
+    }
+
+    /// Takes the next `n` elements of the vector, fills them with `T::default()` and returns them
+    /// immediately, e.g. for gaps in sparse output that don't need a worker. Unlike `push`, this
+    /// can be used while shards are outstanding. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn skip_shard_default(&mut self, n: usize)
+    where
+        T: Default,
+    {
+        self.try_skip_shard_default(n).unwrap();
+    }
+
+    /// As for `skip_shard_default`, but returns an error if there is insufficient capacity.
+    /// Nothing is taken on failure.
+    pub fn try_skip_shard_default(&mut self, n: usize) -> Result<(), InsufficientCapacity>
+    where
+        T: Default,
+    {
+        let mut shard = self.try_take_shard(n).ok_or(InsufficientCapacity)?;
+        shard.fill_with(T::default);
+        self.return_shard(shard);
+        Ok(())
+    }
+
+    /// Takes a shard covering all capacity of the vector that hasn't yet been taken. The shard
+    /// will be empty if all capacity has already been taken.
+    pub fn take_remaining(&mut self) -> Shard<'vec, T> {
+        self.take_shard(self.remaining_capacity())
+    }
+
+    /// Divides the capacity that hasn't yet been taken into `n` shards whose sizes differ by at
+    /// most one. Panics if `n` is zero.
+    pub fn split_even(&mut self, n: usize) -> Vec<Shard<'vec, T>> {
+        assert!(n > 0, "Can't split into zero shards");
+        let remaining = self.remaining_capacity();
+        let (size, extra) = (remaining / n, remaining % n);
+        (0..n)
+            .map(|i| self.take_shard(size + usize::from(i < extra)))
+            .collect()
+    }
+
+    /// As for `split_even`, but moves the boundaries between shards to the nearest cache line
+    /// boundary, so that threads writing adjacent shards don't contend for the same cache line.
+    /// Shard sizes may then differ by up to a cache line's worth of elements. Falls back to
+    /// `split_even` if elements don't evenly divide cache lines.
+    pub fn split_even_aligned(&mut self, n: usize) -> Vec<Shard<'vec, T>> {
+        assert!(n > 0, "Can't split into zero shards");
+        let size = size_of::<T>();
+        let misalignment = self.storage.as_ptr() as usize % CACHE_LINE_SIZE;
+        if size == 0 || !CACHE_LINE_SIZE.is_multiple_of(size) || !misalignment.is_multiple_of(size)
+        {
+            return self.split_even(n);
+        }
+        let line = CACHE_LINE_SIZE / size;
+        // Element `offset` starts a cache line when `offset + misalignment` is a multiple of
+        // `line`.
+        let misalignment = misalignment / size;
+        let start = self.taken;
+        let end = start + self.remaining_capacity();
+        let mut previous = start;
+        let mut shards = Vec::with_capacity(n);
+        for i in 1..=n {
+            let ideal = start + (end - start) * i / n;
+            let aligned = (ideal + misalignment + line / 2) / line * line;
+            let boundary = if i == n {
+                end
+            } else {
+                aligned.saturating_sub(misalignment).clamp(previous, end)
+            };
+            shards.push(self.take_shard(boundary - previous));
+            previous = boundary;
+        }
+        shards
+    }
+
+    /// Returns an iterator that takes shards of `chunk_size` elements until all capacity has been
+    /// taken. The last shard may be shorter. Panics if `chunk_size` is zero.
+    pub fn shards_of_size(
+        &mut self,
+        chunk_size: usize,
+    ) -> impl Iterator<Item = Shard<'vec, T>> + use<'_, 'vec, T, S> {
+        assert!(chunk_size > 0, "Chunk size must be non-zero");
+        std::iter::from_fn(move || {
+            let remaining = self.remaining_capacity();
+            (remaining > 0).then(|| self.take_shard(chunk_size.min(remaining)))
+        })
+    }
+
+    /// Takes shards with the specified sizes, in order. Panics without taking anything if there's
+    /// insufficient capacity for all of them.
+    #[track_caller]
+    pub fn take_shards_by_sizes(&mut self, sizes: &[usize]) -> Vec<Shard<'vec, T>> {
+        self.try_take_shards_by_sizes(sizes).unwrap_or_else(|| {
+            panic!(
+                "Tried to take shards totalling more than the {} available",
+                self.remaining_capacity()
+            );
+        })
+    }
+
+    /// As for `take_shards_by_sizes`, but returns None without taking anything if there's
+    /// insufficient capacity.
+    pub fn try_take_shards_by_sizes(&mut self, sizes: &[usize]) -> Option<Vec<Shard<'vec, T>>> {
+        let total = sizes
+            .iter()
+            .try_fold(0_usize, |total, &size| total.checked_add(size))?;
+        if total > self.remaining_capacity() {
+            return None;
+        }
+        Some(sizes.iter().map(|&size| self.take_shard(size)).collect())
+    }
+
+    /// Takes shards with the specified sizes, which are laid out in the vector in the order given,
+    /// but returns them sorted largest first, each paired with its index in `sizes`. Processing
+    /// the largest shards first reduces the time spent waiting for stragglers when shard sizes are
+    /// skewed. Returns None without taking anything if there's insufficient capacity.
+    pub fn take_shards_largest_first(
+        &mut self,
+        sizes: &[usize],
+    ) -> Option<Vec<(usize, Shard<'vec, T>)>> {
+        let mut shards: Vec<_> = self
+            .try_take_shards_by_sizes(sizes)?
+            .into_iter()
+            .enumerate()
+            .collect();
+        shards.sort_by_key(|(index, _)| std::cmp::Reverse(sizes[*index]));
+        Some(shards)
+    }
+
+    /// Returns a copy of the part of the vector that has been initialised so far, i.e. its
+    /// original contents plus the contents of all shards returned so far. Outstanding shards can
+    /// continue to be filled while this runs.
+    pub fn snapshot_initialised(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.initialised().to_vec()
+    }
+
+    /// Returns the part of the vector that has been initialised so far, i.e. its original contents
+    /// plus the contents of all shards returned so far. This allows processing of the completed
+    /// prefix to start while later shards are still being filled. The slice can be shared with
+    /// other threads, but no shards can be returned until it's no longer in use.
+    pub fn initialised(&self) -> &[T] {
+        // Safety: The initialised part of the storage isn't modified while we're borrowed.
+        unsafe { std::slice::from_raw_parts(self.storage.as_ptr(), self.storage.len()) }
+    }
+
+    /// Returns a shard to the vector. The shard must have been fully initialised before being
+    /// returned. Shards can be returned in any order. The initialised length of the vector advances
+    /// whenever all shards up to some point have been returned. Shards that have been returned
+    /// after a gap are leaked if the writer is dropped before the gap is filled. Panics on failure.
     #[track_caller]
     pub fn return_shard(&mut self, shard: Shard<T>) {
         self.try_return_shard(shard).unwrap()
@@ -85,24 +695,151 @@ impl<'vec, T> VecWriter<'vec, T> {
 
     /// As for `return_shard`, but returns an error on failure rather than panicking.
     pub fn try_return_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
-        if self.storage.as_mut_ptr() != shard.storage {
-            return Err(InitError::WrongVec);
+        self.return_shard_impl(shard)
+            .inspect_err(trace::return_failed)
+    }
+
+    fn return_shard_impl(&mut self, mut shard: Shard<T>) -> Result<(), InitError> {
+        if !self.owns(&shard) {
+            return Err(shard.wrong_vec_error());
         }
-        if shard.initialised_up_to != shard.end_offset {
-            return Err(InitError::UninitElements);
+        shard.check_returnable()?;
+        self.validate_return(&shard)?;
+        self.accept_shard(shard);
+        Ok(())
+    }
+
+    /// Makes the values of a shard that has passed all checks part of the vec.
+    pub(crate) fn accept_shard(&mut self, mut shard: Shard<T>) {
+        if let Some(processor) = self.return_processor.as_mut() {
+            processor(shard.start_offset..shard.end_offset, shard.written_mut());
         }
-        if self.storage.len() != shard.start_offset {
-            return Err(InitError::OutOfOrder);
+        if let (Some(progress), None) = (&self.progress, &shard.progress) {
+            progress.add_written(shard.capacity());
+        }
+        self.patches.append(&mut shard.patches);
+        self.boundaries.push(shard.start_offset..shard.end_offset);
+        if self.storage.len() == shard.start_offset {
+            let mut new_len = shard.end_offset;
+            while let Some(end) = self.pending.remove(&new_len) {
+                new_len = end;
+            }
+            // Safety: All values between the previous length and the new length were set by
+            // writes in `try_push`, either to this shard or to previously returned shards.
+            unsafe { self.storage.set_len(new_len) };
+            if let Some(notifier) = &self.return_notifier {
+                notifier.notify_returned_up_to(new_len);
+            }
+        } else {
+            self.pending.insert(shard.start_offset, shard.end_offset);
         }
-        // Safety: All values between the previous length and the new length were set by writes in
-        // `try_push`.
-        unsafe { self.storage.set_len(shard.initialised_up_to) };
+        trace::shard_returned(&shard.range());
 
         // The values written into the shard are now owned by the vec, so forget the shard without
         // dropping it, otherwise it'll double-free the values in the shard.
         core::mem::forget(shard);
+    }
+
+    /// Returns a batch of shards, e.g. collected from worker threads, in any order. The shards
+    /// must cover a contiguous range of the output once sorted. Either all of the shards are
+    /// returned or, if any is uninitialised, fails validation or there's a gap between them, none
+    /// are. Panics on failure.
+    #[track_caller]
+    pub fn return_shards(&mut self, shards: impl IntoIterator<Item = Shard<'vec, T>>) {
+        self.try_return_shards(shards).unwrap()
+    }
+
+    /// As for `return_shards`, but returns an error on failure rather than panicking. On failure,
+    /// all the shards are dropped.
+    pub fn try_return_shards(
+        &mut self,
+        shards: impl IntoIterator<Item = Shard<'vec, T>>,
+    ) -> Result<(), InitError> {
+        let mut shards: Vec<_> = shards.into_iter().collect();
+        shards.sort_by_key(|shard| shard.start_offset);
+        let mut previous_end = None;
+        for shard in &mut shards {
+            if !self.owns(shard) {
+                return Err(shard.wrong_vec_error());
+            }
+            if let Some(end) = previous_end.filter(|&end| end != shard.start_offset) {
+                return Err(InitError::OutOfOrder {
+                    missing: end..shard.start_offset,
+                });
+            }
+            shard.check_returnable()?;
+            self.validate_return(shard)?;
+            previous_end = Some(shard.end_offset);
+        }
+        for shard in shards {
+            self.accept_shard(shard);
+        }
         Ok(())
     }
+
+    /// Returns the last shard that was taken, which may be only partially initialised. The length
+    /// of the vec will be set to the end of what was written and no more shards can be taken. Use
+    /// this when the length of the output isn't known until the last shard is written. All other
+    /// shards must have been returned first. Panics on failure.
+    #[track_caller]
+    pub fn return_partial_shard(&mut self, shard: Shard<T>) {
+        self.try_return_partial_shard(shard).unwrap()
+    }
+
+    /// As for `return_partial_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_partial_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        self.return_partial_shard_impl(shard)
+            .inspect_err(trace::return_failed)
+    }
+
+    fn return_partial_shard_impl(&mut self, mut shard: Shard<T>) -> Result<(), InitError> {
+        if !self.owns(&shard) {
+            return Err(shard.wrong_vec_error());
+        }
+        if shard.end_offset != self.taken {
+            return Err(InitError::OutOfOrder {
+                missing: shard.end_offset..self.taken,
+            });
+        }
+        if self.back_taken != 0 {
+            return Err(InitError::OutOfOrder {
+                missing: shard.end_offset..self.back_start(),
+            });
+        }
+        if self.storage.len() != shard.start_offset {
+            return Err(self.missing_shard_error());
+        }
+        if shard.has_published_views() {
+            return Err(InitError::PrefixStillPublished {
+                range: shard.range(),
+            });
+        }
+        shard.end_offset = shard.initialised_up_to;
+        self.taken = shard.end_offset;
+        self.closed = true;
+        self.update_progress_total();
+        self.return_shard_impl(shard)
+    }
+}
+
+impl<T> VecWriter<'_, T> {
+    /// Reserves capacity for at least `additional` more elements beyond those already taken, as
+    /// for `Vec::reserve`. Since this may reallocate the vec, it panics if any shards are
+    /// outstanding, anything taken from the back hasn't yet joined the front or any views created
+    /// by `return_and_freeze` still exist.
+    #[track_caller]
+    pub fn reserve(&mut self, additional: usize) {
+        assert!(
+            self.all_returned(),
+            "Can't reserve while shards are outstanding"
+        );
+        assert!(
+            !self.has_frozen_views(),
+            "Can't reserve while views of returned shards exist"
+        );
+        self.storage.reserve(additional);
+        self.update_progress_total();
+    }
 }
 
 impl<'builder, T> Shard<'builder, T> {
@@ -119,16 +856,202 @@ impl<'builder, T> Shard<'builder, T> {
         }
         // Safety: The memory we're writing to was allocated by the Vec that we're writing. It's
         // currently uninitialised (not that that matters for safety). It doesn't alias, since all
-        // shards are created non-overlapping.
+        // shards are created non-overlapping. For zero-sized types, the pointer is dangling, but
+        // offsetting it and writing to it are no-ops, so only the count of written values changes.
         unsafe { self.storage.add(self.initialised_up_to).write(value) };
-        self.initialised_up_to += 1;
+        self.mark_written(1);
         Ok(())
     }
 
+    /// Appends a value to the shard without checking whether there's capacity for it, for tight
+    /// loops where the caller has already checked `remaining_capacity`.
+    ///
+    /// # Safety
+    ///
+    /// The shard must not be full.
+    pub unsafe fn push_unchecked(&mut self, value: T) {
+        debug_assert!(self.initialised_up_to < self.end_offset);
+        // Safety: Our caller promises that there's capacity. See `try_push`.
+        unsafe { self.storage.add(self.initialised_up_to).write(value) };
+        self.mark_written(1);
+    }
+
+    /// Drops all values written to the shard so far, so that it can be refilled, e.g. to retry
+    /// work that failed part-way through. Patches queued by the shard are kept. Panics if any views
+    /// created by `publish_prefix` still exist.
+    #[track_caller]
+    pub fn clear(&mut self) {
+        let written: *mut [T] = self.as_mut_slice();
+        // Rewind first, so that if dropping a value panics, we don't drop values twice.
+        self.initialised_up_to = self.start_offset;
+        if let Some(progress) = &self.progress {
+            progress.remove_written(written.len());
+        }
+        // Safety: The values were initialised and we've just marked them as not being, so they
+        // won't be dropped again.
+        unsafe { std::ptr::drop_in_place(written) };
+    }
+
+    /// Records that the next `n` elements have been initialised.
+    pub(crate) fn mark_written(&mut self, n: usize) {
+        self.initialised_up_to += n;
+        if let Some(progress) = &self.progress {
+            progress.add_written(n);
+        }
+    }
+
+    /// Returns the total number of elements that the shard can hold.
+    pub fn capacity(&self) -> usize {
+        self.end_offset - self.start_offset
+    }
+
+    /// Returns the number of elements that can still be pushed to the shard.
+    pub fn remaining_capacity(&self) -> usize {
+        self.end_offset - self.initialised_up_to
+    }
+
+    /// Returns whether the shard has been completely written.
+    pub fn is_full(&self) -> bool {
+        self.initialised_up_to == self.end_offset
+    }
+
+    /// Returns the range of the output vector covered by the shard.
+    pub fn range(&self) -> Range<usize> {
+        self.start_offset..self.end_offset
+    }
+
+    /// Checks that the shard is fully initialised and has no published views, as required for it
+    /// to be returned.
+    pub(crate) fn check_returnable(&mut self) -> Result<(), InitError> {
+        if self.initialised_up_to != self.end_offset {
+            return Err(InitError::UninitElements {
+                range: self.range(),
+                uninit: self.end_offset - self.initialised_up_to,
+            });
+        }
+        if self.has_published_views() {
+            return Err(InitError::PrefixStillPublished {
+                range: self.range(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn wrong_vec_error(&self) -> InitError {
+        InitError::WrongVec {
+            range: self.range(),
+        }
+    }
+
     /// Returns the offset in the output vector at which the next push will write.
     pub fn output_offset(&self) -> usize {
         self.initialised_up_to
     }
+
+    /// Returns the values that have been written to the shard so far.
+    pub fn as_slice(&self) -> &[T] {
+        // Safety: Values from the start of the shard up to `initialised_up_to` have been
+        // initialised and nothing else can modify them while we're borrowed.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.storage.add(self.start_offset),
+                self.initialised_up_to - self.start_offset,
+            )
+        }
+    }
+
+    /// Returns the values that have been written to the shard so far for modification, e.g. to
+    /// backpatch an earlier value. Panics if any views created by `publish_prefix` still exist.
+    #[track_caller]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        assert!(
+            !self.has_published_views(),
+            "Can't modify a shard while views of it are published"
+        );
+        self.written_mut()
+    }
+
+    /// Overwrites the value at `index` within the shard, dropping the previous value, e.g. to patch
+    /// an element on a later pass. Panics if `index` hasn't been written yet or if any views
+    /// created by `publish_prefix` still exist.
+    #[track_caller]
+    pub fn write_at(&mut self, index: usize, value: T) {
+        let written = self.len();
+        assert!(
+            index < written,
+            "Index {index} hasn't been written yet (only {written} written)"
+        );
+        self.as_mut_slice()[index] = value;
+    }
+
+    /// Returns an iterator over the values that have been written to the shard so far for
+    /// modification. Panics if any views created by `publish_prefix` still exist.
+    #[track_caller]
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Returns the values that have been written to the shard so far for modification, without
+    /// checking for published views.
+    fn written_mut(&mut self) -> &mut [T] {
+        // Safety: See `as_slice`. Our caller is responsible for checking that there are no
+        // published views.
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.storage.add(self.start_offset),
+                self.initialised_up_to - self.start_offset,
+            )
+        }
+    }
+}
+
+impl<T> Deref for Shard<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> Debug for Shard<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shard")
+            .field("range", &self.range())
+            .field("written", &self.len())
+            .field("remaining", &self.remaining_capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, S: ShardableStorage<T>> Debug for VecWriter<'_, T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VecWriter")
+            .field("initialised", &self.storage.len())
+            .field("taken", &self.taken)
+            .field("capacity", &self.storage.capacity())
+            .field("returned_after_gap", &self.pending)
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Indexes the values that have been written to the shard so far.
+impl<T, I: SliceIndex<[T]>> Index<I> for Shard<'_, T> {
+    type Output = I::Output;
+
+    #[track_caller]
+    fn index(&self, index: I) -> &I::Output {
+        &self.as_slice()[index]
+    }
+}
+
+/// Indexes the values that have been written to the shard so far for modification. Panics if any
+/// views created by `publish_prefix` still exist.
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for Shard<'_, T> {
+    #[track_caller]
+    fn index_mut(&mut self, index: I) -> &mut I::Output {
+        &mut self.as_mut_slice()[index]
+    }
 }
 
 /// Insufficient capacity for operation.
@@ -141,25 +1064,65 @@ impl Display for InsufficientCapacity {
     }
 }
 
+/// The remaining capacity of a writer or shard wasn't what was expected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CapacityMismatch {
+    pub expected: usize,
+    pub actual: usize,
+}
+impl Error for CapacityMismatch {}
+impl Display for CapacityMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected remaining capacity of {}, but found {}",
+            self.expected, self.actual
+        )
+    }
+}
+
 /// An error that can occur when returning a shard to a writer.
 #[derive(Debug, PartialEq, Eq)]
 pub enum InitError {
-    /// One or more elements weren't initialised.
-    UninitElements,
+    /// `uninit` elements of the shard covering `range` weren't initialised.
+    UninitElements { range: Range<usize>, uninit: usize },
+
+    /// The shard covering `range` was returned to a writer other than the one that created it.
+    WrongVec { range: Range<usize> },
+
+    /// A shard was missing, e.g. a shard that should have been returned before finishing.
+    /// `missing` is the first range of the output that hasn't been returned.
+    OutOfOrder { missing: Range<usize> },
 
-    /// A shard was returned to a writer other than the one that created it.
-    WrongVec,
+    /// Views created by `Shard::publish_prefix` still existed when the shard covering `range` was
+    /// returned.
+    PrefixStillPublished { range: Range<usize> },
 
-    /// Shards were returned out-of-order or a shard was missing.
-    OutOfOrder,
+    /// The validator registered with `VecWriter::set_return_validator` rejected the contents of
+    /// the shard covering `range`.
+    ValidationFailed {
+        range: Range<usize>,
+        message: String,
+    },
 }
 impl Error for InitError {}
 impl Display for InitError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            InitError::UninitElements => write!(f, "Elements not initialised"),
-            InitError::WrongVec => write!(f, "Shard returned to wrong vec"),
-            InitError::OutOfOrder => write!(f, "Shards returned out-of-order"),
+            InitError::UninitElements { range, uninit } => {
+                write!(
+                    f,
+                    "Elements not initialised in shard {range:?} ({uninit} uninitialised)"
+                )
+            }
+            InitError::WrongVec { range } => write!(f, "Shard {range:?} returned to wrong vec"),
+            InitError::OutOfOrder { missing } => write!(f, "Shard missing for {missing:?}"),
+            InitError::PrefixStillPublished { range } => {
+                write!(f, "Published prefix of shard {range:?} still in use")
+            }
+            InitError::ValidationFailed { range, message } => {
+                write!(f, "Shard {range:?} failed validation: {message}")
+            }
         }
     }
 }
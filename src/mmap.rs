@@ -0,0 +1,60 @@
+//! Writing directly into a memory-mapped file, so that large outputs don't need to be built in
+//! memory first.
+
+use crate::ShardableStorage;
+use memmap2::MmapMut;
+
+/// Storage backed by a writable memory map, e.g. of an output file that has been resized to the
+/// size of the output. Shards write directly into the mapped file. The existing contents of the map
+/// are treated as uninitialised. Use `len` to find out how many bytes were written and
+/// `into_inner` to get the map back, e.g. to flush it.
+pub struct MmapStorage {
+    map: MmapMut,
+    len: usize,
+}
+
+impl MmapStorage {
+    /// Creates storage over `map`, none of which is initialised.
+    pub fn new(map: MmapMut) -> Self {
+        Self { map, len: 0 }
+    }
+
+    /// Returns the number of bytes at the start of the map that have been written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether no bytes have been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the underlying map.
+    pub fn into_inner(self) -> MmapMut {
+        self.map
+    }
+}
+
+// Safety: The map's address and length can't change while it's borrowed, and we only increase
+// `len` via `set_len`, whose caller promises that the bytes are initialised.
+unsafe impl ShardableStorage<u8> for MmapStorage {
+    fn as_ptr(&self) -> *const u8 {
+        self.map.as_ptr()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.map.as_mut_ptr()
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.len()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        self.len = len;
+    }
+}
@@ -0,0 +1,143 @@
+use crate::FinishError;
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+/// Builds a `Vec<T>` representing a square 2D grid, with elements stored in Morton (Z-order) order.
+/// The grid is split into square blocks that are each contiguous in the vec and which can be
+/// initialised separately, most likely from separate threads.
+pub struct MortonWriter<'vec, T> {
+    writer: VecWriter<'vec, T>,
+
+    /// The number of elements in the grid. The vec may have more capacity, which isn't used.
+    grid_size: usize,
+}
+
+/// A square block of a Morton-ordered grid. Behaves like a regular shard, with values being pushed
+/// in Z-order, but can additionally report the grid coordinates of the next value.
+pub struct MortonBlock<'vec, T> {
+    shard: Shard<'vec, T>,
+}
+
+impl<'vec, T> MortonWriter<'vec, T> {
+    /// Creates a new writer for a grid `2^side_log2` elements on each side. Panics if `storage`
+    /// isn't empty or doesn't have sufficient capacity for the grid. Any capacity beyond the grid
+    /// isn't used.
+    pub fn new(storage: &'vec mut Vec<T>, side_log2: u32) -> Self {
+        assert!(storage.is_empty(), "MortonWriter requires an empty Vec");
+        let required = 1_usize
+            .checked_shl(2 * side_log2)
+            .expect("Grid size overflowed");
+        assert!(
+            storage.capacity() >= required,
+            "Capacity {} is less than the {required} required",
+            storage.capacity()
+        );
+        Self {
+            writer: VecWriter::new(storage),
+            grid_size: required,
+        }
+    }
+
+    /// Takes the next block, which will be `2^side_log2` elements on each side. Panics if there's
+    /// insufficient capacity or if the next block wouldn't be aligned to its size.
+    pub fn take_block(&mut self, side_log2: u32) -> MortonBlock<'vec, T> {
+        self.try_take_block(side_log2)
+            .unwrap_or_else(|| panic!("Cannot take a block with side 2^{side_log2}"))
+    }
+
+    /// Takes the next block or returns None if the block would extend beyond the end of the grid
+    /// or if the next block wouldn't be aligned to its size. Blocks are only square if they're
+    /// aligned, so blocks should be taken in decreasing size order.
+    pub fn try_take_block(&mut self, side_log2: u32) -> Option<MortonBlock<'vec, T>> {
+        let size = 1_usize.checked_shl(2 * side_log2)?;
+        if !self.writer.taken.is_multiple_of(size) || size > self.grid_size - self.writer.taken {
+            return None;
+        }
+        Some(MortonBlock {
+            shard: self.writer.try_take_shard(size)?,
+        })
+    }
+
+    /// Returns a block to the vector. See `VecWriter::return_shard`.
+    #[track_caller]
+    pub fn return_block(&mut self, block: MortonBlock<T>) {
+        self.writer.return_shard(block.shard);
+    }
+
+    /// As for `return_block`, but returns an error on failure rather than panicking.
+    pub fn try_return_block(&mut self, block: MortonBlock<T>) -> Result<(), InitError> {
+        self.writer.try_return_shard(block.shard)
+    }
+
+    /// Checks that blocks covering the whole grid have been taken and returned.
+    pub fn finish(self) -> Result<(), FinishError> {
+        let missing = if self.writer.all_returned() {
+            self.writer.taken..self.grid_size
+        } else {
+            self.writer.missing_range()
+        };
+        if !missing.is_empty() {
+            return Err(FinishError { missing });
+        }
+        self.writer.finish().expect("All blocks were returned");
+        Ok(())
+    }
+}
+
+impl<T> MortonBlock<'_, T> {
+    /// Returns the grid coordinates of the element that the next push will write.
+    pub fn position(&self) -> (usize, usize) {
+        morton_coords(self.shard.output_offset())
+    }
+}
+
+impl<'vec, T> Deref for MortonBlock<'vec, T> {
+    type Target = Shard<'vec, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shard
+    }
+}
+
+impl<T> DerefMut for MortonBlock<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shard
+    }
+}
+
+/// Returns the index in a Morton-ordered vec of the element at grid coordinates `x`, `y`.
+pub fn morton_index(x: usize, y: usize) -> usize {
+    (spread_bits(x as u64) | (spread_bits(y as u64) << 1)) as usize
+}
+
+/// Returns the grid coordinates of the element at `index` in a Morton-ordered vec.
+pub fn morton_coords(index: usize) -> (usize, usize) {
+    let index = index as u64;
+    (
+        compact_bits(index) as usize,
+        compact_bits(index >> 1) as usize,
+    )
+}
+
+/// Spreads the low 32 bits of `v` out so that they occupy the even bits of the result.
+fn spread_bits(mut v: u64) -> u64 {
+    v &= 0xffff_ffff;
+    v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+    v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+    v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+    (v | (v << 1)) & 0x5555_5555_5555_5555
+}
+
+/// The inverse of `spread_bits`. Gathers the even bits of `v` into the low 32 bits of the result.
+fn compact_bits(mut v: u64) -> u64 {
+    v &= 0x5555_5555_5555_5555;
+    v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+    v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+    v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+    (v | (v >> 16)) & 0xffff_ffff
+}
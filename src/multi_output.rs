@@ -0,0 +1,92 @@
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Display;
+
+/// Identifies one of the outputs of a `MultiOutputWriter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutputId(usize);
+
+/// Writes several outputs at once, e.g. an executable, its split debug info and a map file. Shards
+/// can be taken from any output and are routed back to the right output when returned.
+pub struct MultiOutputWriter<'vec, T> {
+    pub(crate) writers: Vec<VecWriter<'vec, T>>,
+}
+
+impl<'vec, T> MultiOutputWriter<'vec, T> {
+    /// Creates a new writer with no outputs.
+    pub fn new() -> Self {
+        Self {
+            writers: Vec::new(),
+        }
+    }
+
+    /// Adds an output that will be written into the supplied `Vec`.
+    pub fn add_output(&mut self, storage: &'vec mut Vec<T>) -> OutputId {
+        self.writers.push(VecWriter::new(storage));
+        OutputId(self.writers.len() - 1)
+    }
+
+    /// Takes the next `n` elements of the specified output or panics if there is insufficient
+    /// capacity.
+    pub fn take_shard(&mut self, output: OutputId, n: usize) -> Shard<'vec, T> {
+        self.writers[output.0].take_shard(n)
+    }
+
+    /// Takes the next `n` elements of the specified output or returns None if there is
+    /// insufficient capacity.
+    pub fn try_take_shard(&mut self, output: OutputId, n: usize) -> Option<Shard<'vec, T>> {
+        self.writers[output.0].try_take_shard(n)
+    }
+
+    /// Returns a shard to whichever output it was taken from. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: Shard<T>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        let writer = self
+            .writers
+            .iter_mut()
+            .find(|writer| writer.owns(&shard))
+            .ok_or_else(|| shard.wrong_vec_error())?;
+        writer.try_return_shard(shard)
+    }
+
+    /// Checks that every shard taken from every output has been returned.
+    pub fn finish(self) -> Result<(), IncompleteOutputError> {
+        for (index, writer) in self.writers.iter().enumerate() {
+            if !writer.all_returned() {
+                return Err(IncompleteOutputError {
+                    output: OutputId(index),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for MultiOutputWriter<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An output of a `MultiOutputWriter` had shards taken that were never returned.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IncompleteOutputError {
+    pub output: OutputId,
+}
+impl Error for IncompleteOutputError {}
+impl Display for IncompleteOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Output {} has shards that weren't returned",
+            self.output.0
+        )
+    }
+}
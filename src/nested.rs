@@ -0,0 +1,122 @@
+//! Support for splitting a shard further, for when there are two levels of parallelism, e.g. one
+//! shard per NUMA node, then one sub-shard per core.
+
+use crate::next_writer_id;
+use crate::InitError;
+use crate::Shard;
+use std::collections::BTreeMap;
+
+/// A writer over just the unwritten part of a single shard. Created by `Shard::into_writer`.
+/// Sub-shards taken from it must be returned to it, not to the writer that the original shard came
+/// from. Once all sub-shards have been returned, `into_shard` gives back the original shard.
+pub struct SubWriter<'vec, T> {
+    shard: Shard<'vec, T>,
+
+    /// Identifies sub-shards taken from this writer.
+    id: usize,
+
+    /// The offset in the output vector up to which sub-shards have been taken.
+    taken: usize,
+
+    /// Sub-shards that have been returned after a gap. Maps start offset to end offset.
+    pending: BTreeMap<usize, usize>,
+}
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Converts this shard into a writer over the part of the shard that hasn't yet been written,
+    /// so that it can be split further among sub-tasks.
+    pub fn into_writer(self) -> SubWriter<'vec, T> {
+        SubWriter {
+            taken: self.initialised_up_to,
+            shard: self,
+            id: next_writer_id(),
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'vec, T> SubWriter<'vec, T> {
+    /// Returns the number of elements of the shard that haven't yet been taken.
+    pub fn remaining_capacity(&self) -> usize {
+        self.shard.end_offset - self.taken
+    }
+
+    /// Takes the next `n` elements of the shard or panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn take_shard(&mut self, n: usize) -> Shard<'vec, T> {
+        self.try_take_shard(n).unwrap_or_else(|| {
+            panic!(
+                "Tried to take {n} when only {} available",
+                self.remaining_capacity()
+            );
+        })
+    }
+
+    /// Takes the next `n` elements of the shard or returns None if there is insufficient capacity.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<Shard<'vec, T>> {
+        if n > self.remaining_capacity() {
+            return None;
+        }
+        let end_offset = self.taken + n;
+        let sub_shard = Shard {
+            storage: self.shard.storage,
+            writer_id: self.id,
+            start_offset: self.taken,
+            end_offset,
+            initialised_up_to: self.taken,
+            patches: Vec::new(),
+            published: None,
+            // Values written by sub-shards are counted as they're written, so the parent shard
+            // doesn't count them again when they're returned.
+            progress: self.shard.progress.clone(),
+            _phantom: Default::default(),
+        };
+        self.taken = end_offset;
+        Some(sub_shard)
+    }
+
+    /// Returns a sub-shard, which must have been fully initialised. As for
+    /// `VecWriter::return_shard`, sub-shards can be returned in any order. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, sub_shard: Shard<T>) {
+        self.try_return_shard(sub_shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, mut sub_shard: Shard<T>) -> Result<(), InitError> {
+        if sub_shard.writer_id != self.id {
+            return Err(sub_shard.wrong_vec_error());
+        }
+        sub_shard.check_returnable()?;
+        self.shard.patches.append(&mut sub_shard.patches);
+        if self.shard.initialised_up_to == sub_shard.start_offset {
+            let mut new_end = sub_shard.end_offset;
+            while let Some(end) = self.pending.remove(&new_end) {
+                new_end = end;
+            }
+            self.shard.initialised_up_to = new_end;
+        } else {
+            self.pending
+                .insert(sub_shard.start_offset, sub_shard.end_offset);
+        }
+
+        // The values are now owned by the parent shard.
+        core::mem::forget(sub_shard);
+        Ok(())
+    }
+
+    /// Converts back into the original shard, with everything written by the sub-shards. Any
+    /// capacity that wasn't taken can still be written via the shard. Fails if any sub-shards are
+    /// outstanding, in which case the writer is dropped, dropping everything written so far, but
+    /// leaking anything returned after a gap.
+    pub fn into_shard(self) -> Result<Shard<'vec, T>, InitError> {
+        if self.shard.initialised_up_to != self.taken {
+            let start = self.shard.initialised_up_to;
+            let end = self.pending.keys().next().copied().unwrap_or(self.taken);
+            return Err(InitError::OutOfOrder {
+                missing: start..end,
+            });
+        }
+        Ok(self.shard)
+    }
+}
@@ -0,0 +1,57 @@
+//! Running worker code on a shard without letting a panic take down the whole build.
+
+use crate::Shard;
+use std::any::Any;
+use std::error::Error;
+use std::fmt::Display;
+use std::ops::Range;
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Calls `f` to fill the shard, catching any panic. If `f` returns normally, the shard is
+    /// given back so that it can be returned to the writer. If it panics, anything written to the
+    /// shard is dropped and the panic is reported as an error, which can be sent back to the owner
+    /// of the writer.
+    pub fn run(mut self, f: impl FnOnce(&mut Self)) -> Result<Self, WorkerPanicked> {
+        let range = self.range();
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut self))) {
+            Ok(()) => Ok(self),
+            Err(payload) => {
+                // Drop the shard before its values are referenced by anything else.
+                drop(self);
+                Err(WorkerPanicked { range, payload })
+            }
+        }
+    }
+}
+
+/// A worker panicked while filling a shard. Returned by `Shard::run`.
+#[derive(Debug)]
+pub struct WorkerPanicked {
+    /// The range of the output covered by the shard.
+    pub range: Range<usize>,
+
+    /// The value that the worker panicked with. Can be passed to `std::panic::resume_unwind` to
+    /// continue the panic on another thread.
+    pub payload: Box<dyn Any + Send>,
+}
+
+impl WorkerPanicked {
+    /// Returns the panic message, if the worker panicked with a string.
+    pub fn message(&self) -> Option<&str> {
+        self.payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+}
+
+impl Error for WorkerPanicked {}
+impl Display for WorkerPanicked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Worker for shard {:?} panicked", self.range)?;
+        if let Some(message) = self.message() {
+            write!(f, ": {message}")?;
+        }
+        Ok(())
+    }
+}
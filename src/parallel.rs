@@ -0,0 +1,64 @@
+//! Internal helpers for splitting work between threads.
+
+/// Splits `items` into contiguous groups, one per available thread, and calls `f` on each item.
+/// Items within a group are processed in order. Runs on the current thread if there's only one
+/// group.
+pub(crate) fn for_each_in_parallel<I: Send>(items: Vec<I>, f: impl Fn(I) + Sync) {
+    map_in_parallel(items, f);
+}
+
+/// As for `for_each_in_parallel`, but collects the results of `f`, in the same order as `items`.
+pub(crate) fn map_in_parallel<I: Send, O: Send>(
+    items: Vec<I>,
+    f: impl Fn(I) -> O + Sync,
+) -> Vec<O> {
+    let num_threads = available_threads().min(items.len());
+    if num_threads <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+    let group_size = items.len().div_ceil(num_threads);
+    let mut items = items.into_iter();
+    let f = &f;
+    std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        loop {
+            let group: Vec<I> = items.by_ref().take(group_size).collect();
+            if group.is_empty() {
+                break;
+            }
+            handles.push(scope.spawn(move || group.into_iter().map(f).collect::<Vec<O>>()));
+        }
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+            })
+            .collect()
+    })
+}
+
+/// Returns the number of threads that we should split work between.
+pub(crate) fn available_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// A raw pointer that we've promised to only use to access disjoint parts of an allocation from
+/// different threads.
+pub(crate) struct SendPtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for SendPtr<T> {}
+unsafe impl<T: Send> Sync for SendPtr<T> {}
+
+impl<T> SendPtr<T> {
+    pub(crate) fn new(ptr: *mut T) -> Self {
+        Self(ptr)
+    }
+
+    /// Returns the pointer. This is a method rather than a public field so that closures capture
+    /// the whole `SendPtr` rather than just the non-`Send` pointer inside it.
+    pub(crate) fn get(&self) -> *mut T {
+        self.0
+    }
+}
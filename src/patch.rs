@@ -0,0 +1,99 @@
+use crate::parallel::for_each_in_parallel;
+use crate::parallel::SendPtr;
+use crate::Shard;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Display;
+
+/// A write to part of the output that has been deferred until all shards have been returned.
+pub(crate) struct Patch<T> {
+    offset: usize,
+    values: Vec<T>,
+}
+
+impl<T> Shard<'_, T> {
+    /// Queues a write of `values` starting at `offset` in the output vector. `offset` will
+    /// typically be within a region owned by some other shard, e.g. for a reference to something
+    /// that another thread is writing. The patch travels with the shard and is applied by
+    /// `VecWriter::apply_patches` once all shards have been returned.
+    pub fn queue_patch(&mut self, offset: usize, values: Vec<T>) {
+        self.patches.push(Patch { offset, values });
+    }
+}
+
+impl<T: Send> VecWriter<'_, T> {
+    /// Applies all patches queued by returned shards, overwriting (and dropping) the values
+    /// previously written at the patched locations. Patches are applied in parallel. All shards
+    /// that were taken must have been returned first. On error, no patches are applied. If the
+    /// error was due to outstanding shards or frozen views, the patches remain queued, otherwise
+    /// they're discarded.
+    pub fn apply_patches(&mut self) -> Result<(), PatchError> {
+        if !self.all_returned() {
+            return Err(PatchError::OutstandingShards);
+        }
+        if self.has_frozen_views() {
+            return Err(PatchError::FrozenViews);
+        }
+        let mut patches = core::mem::take(&mut self.patches);
+        patches.sort_by_key(|patch| patch.offset);
+        let mut previous_end = 0;
+        for patch in &patches {
+            let end = patch.offset.saturating_add(patch.values.len());
+            if end > self.storage.len() {
+                return Err(PatchError::OutOfBounds {
+                    offset: patch.offset,
+                    len: patch.values.len(),
+                });
+            }
+            if patch.offset < previous_end {
+                return Err(PatchError::Overlap {
+                    offset: patch.offset,
+                });
+            }
+            previous_end = end;
+        }
+
+        let base = SendPtr::new(self.storage.as_mut_ptr());
+        for_each_in_parallel(patches, |patch| {
+            for (i, value) in patch.values.into_iter().enumerate() {
+                // Safety: We checked above that the patch is within the initialised part of the
+                // vec and that no two patches overlap, so no other thread is accessing this
+                // element.
+                unsafe { *base.get().add(patch.offset + i) = value };
+            }
+        });
+        Ok(())
+    }
+}
+
+/// An error that can occur when applying patches.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// Not all shards that were taken have been returned.
+    OutstandingShards,
+
+    /// Views created by `VecWriter::return_and_freeze` still exist.
+    FrozenViews,
+
+    /// A patch extended beyond the initialised part of the vector.
+    OutOfBounds { offset: usize, len: usize },
+
+    /// Two patches wrote to the same element.
+    Overlap { offset: usize },
+}
+impl Error for PatchError {}
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::OutstandingShards => write!(f, "Shards still outstanding"),
+            PatchError::FrozenViews => write!(f, "Views of returned shards still exist"),
+            PatchError::OutOfBounds { offset, len } => {
+                write!(
+                    f,
+                    "Patch of {len} elements at offset {offset} is out of bounds"
+                )
+            }
+            PatchError::Overlap { offset } => write!(f, "Overlapping patch at offset {offset}"),
+        }
+    }
+}
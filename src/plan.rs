@@ -0,0 +1,65 @@
+//! A macro for fixed layouts, where the size of each shard is known at compile time.
+
+/// Declares a struct with a field for each shard of a fixed layout. The sizes of the shards are
+/// checked at compile time to sum to the declared capacity. The generated struct has a `take`
+/// method that takes each shard, in order, from a writer and a `return_all` method that returns
+/// them all.
+///
+/// ```
+/// sharded_vec_writer::shard_plan! {
+///     pub struct Layout: [u8; 12] {
+///         header: 4,
+///         body: 8,
+///     }
+/// }
+///
+/// let mut v = Vec::with_capacity(Layout::CAPACITY);
+/// let mut writer = sharded_vec_writer::VecWriter::new(&mut v);
+/// let mut layout = Layout::take(&mut writer);
+/// for _ in 0..4 {
+///     layout.header.push(1);
+/// }
+/// for _ in 0..8 {
+///     layout.body.push(2);
+/// }
+/// layout.return_all(&mut writer);
+/// ```
+#[macro_export]
+macro_rules! shard_plan {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: [$elem:ty; $capacity:expr] {
+            $($field:ident: $size:expr),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name<'vec> {
+            $(pub $field: $crate::Shard<'vec, $elem>,)*
+        }
+
+        impl<'vec> $name<'vec> {
+            /// The total number of elements in the layout.
+            pub const CAPACITY: usize = $capacity;
+
+            const _CHECK_SIZES: () = assert!(
+                0 $(+ $size)* == $capacity,
+                "Shard sizes don't sum to the declared capacity"
+            );
+
+            /// Takes each shard of the layout, in order. Panics if there is insufficient capacity.
+            pub fn take(writer: &mut $crate::VecWriter<'vec, $elem>) -> Self {
+                #[allow(clippy::let_unit_value)]
+                let () = Self::_CHECK_SIZES;
+                Self {
+                    $($field: writer.take_shard($size),)*
+                }
+            }
+
+            /// Returns each shard of the layout, in order. Panics on failure.
+            #[track_caller]
+            pub fn return_all(self, writer: &mut $crate::VecWriter<'vec, $elem>) {
+                $(writer.return_shard(self.$field);)*
+            }
+        }
+    };
+}
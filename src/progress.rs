@@ -0,0 +1,80 @@
+//! Reporting how much of the output has been written, e.g. to drive a progress bar.
+
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// A handle that other threads can use to find out how many elements have been written to a
+/// writer's shards so far. Created by `VecWriter::progress_handle`.
+#[derive(Clone)]
+pub struct ProgressHandle {
+    state: Arc<ProgressState>,
+}
+
+struct ProgressState {
+    written: AtomicUsize,
+    total: AtomicUsize,
+}
+
+impl ProgressHandle {
+    /// Returns the number of elements written so far, not counting any that were in the vec before
+    /// the writer was created.
+    pub fn written(&self) -> usize {
+        self.state.written.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of elements that the writer will have written once it's done, i.e. its
+    /// capacity less the length of the vec when the writer was created. This changes if the writer
+    /// reserves more space or a partial shard is returned.
+    pub fn total(&self) -> usize {
+        self.state.total.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_written(&self, n: usize) {
+        self.state.written.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remove_written(&self, n: usize) {
+        self.state.written.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_total(&self, total: usize) {
+        self.state.total.store(total, Ordering::Relaxed);
+    }
+}
+
+impl<T, S: ShardableStorage<T>> VecWriter<'_, T, S> {
+    /// Returns a handle for monitoring how much has been written. Shards taken after this is first
+    /// called update the handle as each value is written. Shards that were already outstanding are
+    /// counted once they're returned.
+    pub fn progress_handle(&mut self) -> ProgressHandle {
+        let written = self.storage.len() - self.original_len
+            + self
+                .pending
+                .iter()
+                .map(|(start, end)| end - start)
+                .sum::<usize>();
+        let total = self.storage.capacity() - self.original_len;
+        self.progress
+            .get_or_insert_with(|| ProgressHandle {
+                state: Arc::new(ProgressState {
+                    written: AtomicUsize::new(written),
+                    total: AtomicUsize::new(total),
+                }),
+            })
+            .clone()
+    }
+
+    pub(crate) fn update_progress_total(&self) {
+        if let Some(progress) = &self.progress {
+            let capacity = if self.closed {
+                self.taken
+            } else {
+                self.storage.capacity()
+            };
+            progress.set_total(capacity - self.original_len);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::Shard;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Weak;
+
+/// A read-only view of values that were written to a shard before `Shard::publish_prefix` was
+/// called. Can be sent to another thread so that processing of a shard's data can start while the
+/// shard is still being filled. While any such view exists, the shard can't be returned and, if the
+/// shard is dropped, the published values are leaked rather than dropped.
+pub struct PublishedPrefix<'vec, T> {
+    start: *const T,
+    range: Range<usize>,
+
+    /// Shared with the shard, so that it can tell when all views have been dropped.
+    _token: Arc<()>,
+
+    _phantom: PhantomData<&'vec T>,
+}
+
+unsafe impl<T: Sync> Send for PublishedPrefix<'_, T> {}
+unsafe impl<T: Sync> Sync for PublishedPrefix<'_, T> {}
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Returns a read-only view of the values written to the shard so far. The shard can continue
+    /// to be filled while the view exists, but values already written can no longer be modified,
+    /// e.g. by a return processor, so the shard can't be returned until all views have been
+    /// dropped.
+    pub fn publish_prefix(&mut self) -> PublishedPrefix<'vec, T> {
+        let token = match self.published.as_ref().and_then(Weak::upgrade) {
+            Some(token) => token,
+            None => {
+                let token = Arc::new(());
+                self.published = Some(Arc::downgrade(&token));
+                token
+            }
+        };
+        PublishedPrefix {
+            // Safety: `start_offset` is within the capacity of the vec.
+            start: unsafe { self.storage.add(self.start_offset) },
+            range: self.start_offset..self.initialised_up_to,
+            _token: token,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns whether any views returned by `publish_prefix` still exist.
+    pub(crate) fn has_published_views(&mut self) -> bool {
+        self.published
+            .as_ref()
+            .is_some_and(|token| token.strong_count() > 0)
+    }
+}
+
+impl<T> PublishedPrefix<'_, T> {
+    /// Returns the range of the output vector covered by this view.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+impl<T> Deref for PublishedPrefix<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // Safety: These values were initialised before we were created and the shard won't modify
+        // or drop them while we exist.
+        unsafe { std::slice::from_raw_parts(self.start, self.range.len()) }
+    }
+}
@@ -0,0 +1,93 @@
+//! Filling shards using a pool of worker threads, without the caller needing to schedule work.
+
+use crate::parallel::available_threads;
+use crate::InitError;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+/// A list of shards to be filled by `VecWriter::process_queue`. Each entry has a size and a
+/// descriptor of the work needed to fill it, e.g. an input file or a range of input.
+pub struct ShardQueue<D> {
+    jobs: Vec<(usize, D)>,
+}
+
+impl<D> ShardQueue<D> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    /// Adds a shard of `size` elements, which will be filled according to `descriptor`. Shards are
+    /// laid out in the output in the order that they're pushed.
+    pub fn push(&mut self, size: usize, descriptor: D) {
+        self.jobs.push((size, descriptor));
+    }
+
+    /// Returns the total number of elements in all shards in the queue.
+    pub fn total_size(&self) -> usize {
+        self.jobs.iter().map(|(size, _)| size).sum()
+    }
+}
+
+impl<D> Default for ShardQueue<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'vec, T: Send + 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Takes a shard for each entry in `queue`, then fills them from a pool of worker threads by
+    /// calling `fill` with each entry's descriptor and shard. Workers take the next entry whenever
+    /// they finish one, so uneven work is balanced between them. Shards are returned to the writer
+    /// as they're completed. Returns the first error from returning a shard, e.g. because `fill`
+    /// didn't fill it, in which case shards that haven't yet been returned are dropped. Panics if
+    /// there's insufficient capacity for the queue. If `fill` panics, the panic is propagated once
+    /// all workers have stopped.
+    #[track_caller]
+    pub fn process_queue<D: Send>(
+        &mut self,
+        queue: ShardQueue<D>,
+        fill: impl Fn(D, &mut Shard<'vec, T>) + Sync,
+    ) -> Result<(), InitError> {
+        let sizes: Vec<usize> = queue.jobs.iter().map(|(size, _)| *size).collect();
+        let shards = self.take_shards_by_sizes(&sizes);
+        let num_jobs = shards.len();
+        let jobs = Mutex::new(
+            queue
+                .jobs
+                .into_iter()
+                .map(|(_, descriptor)| descriptor)
+                .zip(shards),
+        );
+        let (sender, receiver) = mpsc::channel();
+        let fill = &fill;
+        let jobs = &jobs;
+        std::thread::scope(|scope| {
+            for _ in 0..available_threads().min(num_jobs) {
+                let sender = sender.clone();
+                scope.spawn(move || loop {
+                    // Release the lock before filling, so that other workers can take jobs.
+                    let job = jobs.lock().unwrap().next();
+                    let Some((descriptor, mut shard)) = job else {
+                        break;
+                    };
+                    fill(descriptor, &mut shard);
+                    if sender.send(shard).is_err() {
+                        break;
+                    }
+                });
+            }
+            // Once all workers have finished, the channel is disconnected.
+            drop(sender);
+            let result = receiver
+                .iter()
+                .try_for_each(|shard| self.try_return_shard(shard));
+            // Tell workers to stop if we failed.
+            drop(receiver);
+            result
+        })
+    }
+}
@@ -0,0 +1,73 @@
+//! Decomposing shards into raw parts, e.g. so that they can be filled by foreign code.
+
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::marker::PhantomData;
+
+impl<T> Shard<'_, T> {
+    /// Decomposes the shard into a pointer to the start of the output, the start and end offsets
+    /// of the part of the output that the shard covers and the offset up to which the shard has
+    /// been written. Values from the start offset up to the written offset are initialised. The
+    /// shard can be rebuilt with `VecWriter::shard_from_raw_parts`, otherwise anything written to
+    /// it is leaked. Panics if the shard has queued patches or has published views, since these
+    /// can't be represented as raw parts.
+    #[track_caller]
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize, usize) {
+        assert!(
+            self.patches.is_empty(),
+            "Can't convert a shard with patches into raw parts"
+        );
+        assert!(
+            self.published.is_none(),
+            "Can't convert a shard with published views into raw parts"
+        );
+        if let Some(progress) = &self.progress {
+            progress.remove_written(self.initialised_up_to - self.start_offset);
+        }
+        let parts = (
+            self.storage,
+            self.start_offset,
+            self.end_offset,
+            self.initialised_up_to,
+        );
+        // The shard no longer owns the values written to it.
+        std::mem::forget(self);
+        parts
+    }
+}
+
+impl<'vec, T, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Rebuilds a shard from parts returned by `Shard::into_raw_parts`. The written offset may
+    /// have advanced since the shard was decomposed, e.g. because foreign code filled it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr`, `start` and `end` must be as returned by `into_raw_parts` for a shard taken from this
+    /// writer and each set of parts may only be used to rebuild a shard once. `written` must be
+    /// between the returned written offset and `end` and all values from `start` up to `written`
+    /// must be initialised.
+    pub unsafe fn shard_from_raw_parts(
+        &self,
+        ptr: *mut T,
+        start: usize,
+        end: usize,
+        written: usize,
+    ) -> Shard<'vec, T> {
+        debug_assert!(start <= written && written <= end);
+        if let Some(progress) = &self.progress {
+            progress.add_written(written - start);
+        }
+        Shard {
+            storage: ptr,
+            writer_id: self.id,
+            start_offset: start,
+            end_offset: end,
+            initialised_up_to: written,
+            patches: Vec::new(),
+            published: None,
+            progress: self.progress.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
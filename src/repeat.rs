@@ -0,0 +1,36 @@
+use crate::parallel::available_threads;
+use crate::parallel::map_in_parallel;
+use crate::VecWriter;
+
+/// Returns a `Vec` containing `template` repeated `times` times. Repetitions are split between
+/// threads, each of which clones its repetitions directly into its own shard of the output. Panics
+/// if the length of the output would overflow.
+pub fn par_repeat<T: Clone + Send + Sync>(template: &[T], times: usize) -> Vec<T> {
+    let total = template
+        .len()
+        .checked_mul(times)
+        .expect("Repeated length overflowed");
+    let mut output = Vec::with_capacity(total);
+    let mut writer = VecWriter::new(&mut output);
+    let num_shards = available_threads().min(times).max(1);
+    let times_per_shard = times.div_ceil(num_shards).max(1);
+    let mut remaining = times;
+    let mut shards = Vec::new();
+    while remaining > 0 {
+        let shard_times = times_per_shard.min(remaining);
+        shards.push((shard_times, writer.take_shard(shard_times * template.len())));
+        remaining -= shard_times;
+    }
+    let shards = map_in_parallel(shards, |(shard_times, mut shard)| {
+        for _ in 0..shard_times {
+            for value in template {
+                shard.push(value.clone());
+            }
+        }
+        shard
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    output
+}
@@ -0,0 +1,54 @@
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::sync::mpsc;
+
+/// A handle that worker threads can use to return shards themselves, rather than handing them back
+/// to the thread that owns the writer. Can be cloned, so that each worker can have its own.
+pub struct ShardReturner<'vec, T> {
+    sender: mpsc::Sender<Shard<'vec, T>>,
+}
+
+/// Receives shards sent via `ShardReturner`s. Passed to `VecWriter::collect`.
+pub struct ShardCollector<'vec, T> {
+    receiver: mpsc::Receiver<Shard<'vec, T>>,
+}
+
+/// Creates a returner that workers can use to return shards and a collector that the owner of the
+/// writer passes to `VecWriter::collect`.
+pub fn return_channel<'vec, T>() -> (ShardReturner<'vec, T>, ShardCollector<'vec, T>) {
+    let (sender, receiver) = mpsc::channel();
+    (ShardReturner { sender }, ShardCollector { receiver })
+}
+
+impl<'vec, T> ShardReturner<'vec, T> {
+    /// Sends a shard to be returned to the writer by `VecWriter::collect`. If the collector has
+    /// been dropped, then the shard is dropped.
+    pub fn return_shard(&self, shard: Shard<'vec, T>) {
+        let _ = self.sender.send(shard);
+    }
+}
+
+impl<T> Clone for ShardReturner<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<'vec, T> VecWriter<'vec, T> {
+    /// Blocks, returning shards sent via `ShardReturner`s as they arrive, until all shards that
+    /// were taken have been returned. Returns an error if a shard can't be returned or if all
+    /// returners are dropped before all shards have been returned, e.g. because a worker dropped
+    /// its shard.
+    pub fn collect(&mut self, collector: ShardCollector<'vec, T>) -> Result<(), InitError> {
+        while !self.all_returned() {
+            let Ok(shard) = collector.receiver.recv() else {
+                return Err(self.missing_shard_error());
+            };
+            self.try_return_shard(shard)?;
+        }
+        Ok(())
+    }
+}
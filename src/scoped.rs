@@ -0,0 +1,29 @@
+use crate::parallel::map_in_parallel;
+use crate::InitError;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::ops::Range;
+
+impl<'vec, T: Send + 'vec, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Splits all remaining capacity into shards of `chunk_size` elements (the last may be
+    /// shorter), calls `init` on each shard from worker threads, then returns all the shards.
+    /// `init` is passed the range of the output covered by the shard and must fill it completely.
+    /// If `init` panics, the panic is propagated once all threads have finished and values written
+    /// to shards are dropped. Panics if `chunk_size` is zero.
+    pub fn scope_init(
+        &mut self,
+        chunk_size: usize,
+        init: impl Fn(Range<usize>, &mut Shard<'vec, T>) + Sync,
+    ) -> Result<(), InitError> {
+        let shards = self.shards_of_size(chunk_size).collect();
+        let shards = map_in_parallel(shards, |mut shard| {
+            init(shard.range(), &mut shard);
+            shard
+        });
+        for shard in shards {
+            self.try_return_shard(shard)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::Shard;
+use crate::VecWriter;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::Mutex;
+
+/// A pool of scratch buffers that can be shared between threads. Buffers are returned to the pool
+/// when dropped, so that their allocations can be reused by whichever shard is processed next.
+pub struct ScratchPool<S> {
+    buffers: Mutex<Vec<Vec<S>>>,
+}
+
+/// A scratch buffer borrowed from a `ScratchPool`. Derefs to an empty `Vec` with at least the
+/// requested capacity.
+pub struct Scratch<'pool, S> {
+    buffer: Vec<S>,
+    pool: &'pool ScratchPool<S>,
+}
+
+impl<S> ScratchPool<S> {
+    /// Creates a new, empty pool.
+    pub fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns an empty scratch buffer with capacity of at least `size_hint`, reusing a previously
+    /// returned buffer if one is available.
+    pub fn get(&self, size_hint: usize) -> Scratch<'_, S> {
+        let mut buffer = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        buffer.reserve(size_hint);
+        Scratch { buffer, pool: self }
+    }
+}
+
+impl<S> Default for ScratchPool<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'vec, T> VecWriter<'vec, T> {
+    /// Takes the next `n` elements of the vector together with a scratch buffer from `pool` with
+    /// capacity of at least `scratch_size_hint`. Panics if there is insufficient capacity.
+    pub fn take_shard_with_scratch<'pool, S>(
+        &mut self,
+        n: usize,
+        pool: &'pool ScratchPool<S>,
+        scratch_size_hint: usize,
+    ) -> (Shard<'vec, T>, Scratch<'pool, S>) {
+        (self.take_shard(n), pool.get(scratch_size_hint))
+    }
+}
+
+impl<S> Drop for Scratch<'_, S> {
+    fn drop(&mut self) {
+        let mut buffer = core::mem::take(&mut self.buffer);
+        buffer.clear();
+        self.pool.buffers.lock().unwrap().push(buffer);
+    }
+}
+
+impl<S> Deref for Scratch<'_, S> {
+    type Target = Vec<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<S> DerefMut for Scratch<'_, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
@@ -0,0 +1,61 @@
+use crate::InitError;
+use crate::TaggedShard;
+use crate::VecWriter;
+
+/// A shard that will be loaded at a particular address, e.g. a section of an executable. Behaves
+/// like a regular shard, but can additionally convert output offsets into addresses. Addresses are
+/// measured in elements of `T`, so for a byte output, they're byte addresses.
+pub type Section<'vec, T> = TaggedShard<'vec, T, BaseAddress>;
+
+/// The tag of a `Section`, which is the address of its first element. This is a distinct type so
+/// that only sections, not all shards tagged with a `u64`, have methods for working with addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BaseAddress(pub u64);
+
+impl<'vec, T> VecWriter<'vec, T> {
+    /// Takes the next `n` elements of the vector as a section that will be loaded at
+    /// `base_address`. Panics if there is insufficient capacity.
+    pub fn take_section(&mut self, n: usize, base_address: u64) -> Section<'vec, T> {
+        self.take_tagged_shard(n, BaseAddress(base_address))
+    }
+
+    /// As for `take_section`, but returns None if there is insufficient capacity.
+    pub fn try_take_section(&mut self, n: usize, base_address: u64) -> Option<Section<'vec, T>> {
+        self.try_take_tagged_shard(n, BaseAddress(base_address))
+    }
+
+    /// Returns a section to the vector. See `return_shard`.
+    #[track_caller]
+    pub fn return_section(&mut self, section: Section<T>) {
+        self.return_tagged_shard(section);
+    }
+
+    /// As for `return_section`, but returns an error on failure rather than panicking.
+    pub fn try_return_section(&mut self, section: Section<T>) -> Result<(), InitError> {
+        self.try_return_tagged_shard(section)
+            .map(|_| ())
+            .map_err(|error| error.error)
+    }
+}
+
+impl<T> Section<'_, T> {
+    /// Returns the address of the first element of the section.
+    pub fn base_address(&self) -> u64 {
+        self.tag().0
+    }
+
+    /// Returns the address at which the next push will write.
+    pub fn address(&self) -> u64 {
+        self.base_address() + (self.initialised_up_to - self.start_offset) as u64
+    }
+
+    /// Returns the address corresponding to `offset` in the output vector, or None if `offset` is
+    /// outside of this section. The end offset of the section is considered to be inside the
+    /// section, since it's often needed for symbols that mark the end of a section.
+    pub fn address_of_offset(&self, offset: usize) -> Option<u64> {
+        if !(self.start_offset..=self.end_offset).contains(&offset) {
+            return None;
+        }
+        Some(self.base_address() + (offset - self.start_offset) as u64)
+    }
+}
@@ -0,0 +1,139 @@
+use crate::InitError;
+use crate::VecWriter;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+/// Exposes the uninitialised part of a byte writer's vec through `Write` and `Seek`, so that
+/// existing code that requires a seekable sink can write into it. Positions are offsets in the
+/// output vector. Writes are limited to the capacity that could otherwise be taken as shards and
+/// seeking relative to the end is relative to the end of that capacity. Once done, `finish`
+/// checks that the bytes written form a contiguous region directly after the initialised part of
+/// the vec and makes them part of the vec, after which shards can be taken for any remaining
+/// capacity.
+pub struct SeekableBytes<'writer, 'vec> {
+    writer: &'writer mut VecWriter<'vec, u8>,
+    position: u64,
+
+    /// Regions that have been written, keyed by start offset, mapping to exclusive end offset.
+    /// Adjacent and overlapping regions are merged.
+    written: BTreeMap<usize, usize>,
+}
+
+impl<'vec> VecWriter<'vec, u8> {
+    /// Returns an adapter that implements `Write` and `Seek` over the remaining capacity of the
+    /// vec, starting at the current end of the vec. Panics if any shards are outstanding.
+    pub fn seekable(&mut self) -> SeekableBytes<'_, 'vec> {
+        assert!(
+            self.all_returned(),
+            "Can't write while shards are outstanding"
+        );
+        SeekableBytes {
+            position: self.taken as u64,
+            writer: self,
+            written: BTreeMap::new(),
+        }
+    }
+}
+
+impl SeekableBytes<'_, '_> {
+    /// Checks that the bytes written form a single contiguous region starting at the end of the
+    /// vec, then adds them to the vec. Nothing is added on failure.
+    pub fn finish(self) -> Result<(), InitError> {
+        let start = self.writer.storage.len();
+        let mut regions = self.written.iter();
+        let end = match (regions.next(), regions.next()) {
+            (None, _) => start,
+            (Some((&region_start, &region_end)), None) if region_start == start => region_end,
+            _ => {
+                let end = self.written.values().copied().max().unwrap_or(start);
+                let written: usize = self.written.iter().map(|(start, end)| end - start).sum();
+                return Err(InitError::UninitElements {
+                    range: start..end,
+                    uninit: end - start - written,
+                });
+            }
+        };
+        // Safety: Every byte between the old length and `end` was written.
+        unsafe { self.writer.storage.set_len(end) };
+        self.writer.taken = end;
+        Ok(())
+    }
+
+    /// Returns the offset up to which bytes can be written, which is the same limit that applies
+    /// when taking shards.
+    fn end(&self) -> usize {
+        self.writer.taken + self.writer.remaining_capacity()
+    }
+
+    fn record_written(&mut self, mut start: usize, mut end: usize) {
+        // Merge with any region that starts before us and reaches us.
+        if let Some((&previous_start, &previous_end)) = self.written.range(..=start).next_back() {
+            if previous_end >= start {
+                start = previous_start;
+                end = end.max(previous_end);
+            }
+        }
+        // Merge with any regions that start within or directly after us.
+        let following: Vec<(usize, usize)> = self
+            .written
+            .range(start..=end)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in following {
+            self.written.remove(&s);
+            end = end.max(e);
+        }
+        self.written.insert(start, end);
+    }
+}
+
+impl Write for SeekableBytes<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.end();
+        let storage = &mut *self.writer.storage;
+        let Ok(position) = usize::try_from(self.position) else {
+            return Ok(0);
+        };
+        if position < storage.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Can't write to already initialised part of vec",
+            ));
+        }
+        let len = buf.len().min(end.saturating_sub(position));
+        // We might be positioned past the end of the allocation, in which case we mustn't even
+        // compute a pointer to `position`.
+        if len == 0 {
+            return Ok(0);
+        }
+        // Safety: The range being written is within the capacity of the vec, beyond its length and
+        // nothing else is accessing it, since no shards were outstanding when we were created and
+        // we hold a mutable borrow of the writer.
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), storage.as_mut_ptr().add(position), len)
+        };
+        self.record_written(position, position + len);
+        self.position += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SeekableBytes<'_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.end() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = new_position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+        Ok(self.position)
+    }
+}
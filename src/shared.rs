@@ -0,0 +1,142 @@
+//! Taking shards from multiple threads at once, so that workers can take more work as they finish.
+
+use crate::parallel::SendPtr;
+use crate::trace;
+use crate::InitError;
+use crate::ProgressHandle;
+use crate::Shard;
+use crate::ShardableStorage;
+use crate::VecWriter;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// A view of a writer through which shards can be taken and returned via a shared reference, so
+/// that each worker thread can take its next shard when it's ready, rather than one thread
+/// dividing up all the work in advance. Created by `VecWriter::share`. Shards are taken in order of
+/// offset, but different threads can't predict which shards they'll get. When dropped, any capacity
+/// that wasn't taken can again be taken via the writer.
+pub struct SharedVecWriter<'writer, 'vec, T, S: ShardableStorage<T> = Vec<T>> {
+    /// Used only to return shards. Taking shards doesn't need the lock.
+    writer: Mutex<&'writer mut VecWriter<'vec, T, S>>,
+
+    storage: SendPtr<T>,
+
+    /// The offset at which the first shard taken via the view started.
+    start: usize,
+
+    /// The offset at which the next shard will start.
+    next: AtomicUsize,
+
+    /// The end of the capacity that shards can be taken from.
+    end: usize,
+
+    writer_id: usize,
+    progress: Option<ProgressHandle>,
+    _phantom: PhantomData<&'vec mut T>,
+}
+
+impl<'vec, T, S: ShardableStorage<T>> VecWriter<'vec, T, S> {
+    /// Returns a view of the writer through which shards can be taken and returned from multiple
+    /// threads at once. All capacity that hasn't yet been taken can be taken via the view.
+    pub fn share(&mut self) -> SharedVecWriter<'_, 'vec, T, S> {
+        let start = self.taken;
+        let end = start + self.remaining_capacity();
+        // Until the view is dropped, the writer treats everything as having been taken.
+        self.taken = end;
+        SharedVecWriter {
+            storage: SendPtr::new(self.storage.as_mut_ptr()),
+            start,
+            next: AtomicUsize::new(start),
+            end,
+            writer_id: self.id,
+            progress: self.progress.clone(),
+            writer: Mutex::new(self),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'vec, T, S: ShardableStorage<T>> SharedVecWriter<'_, 'vec, T, S> {
+    /// Takes the next `n` elements of the vector or panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn take_shard(&self, n: usize) -> Shard<'vec, T> {
+        self.try_take_shard(n).unwrap_or_else(|| {
+            panic!(
+                "Tried to take {n} when only {} available",
+                self.remaining_capacity()
+            );
+        })
+    }
+
+    /// Takes the next `n` elements of the vector or returns None if there is insufficient capacity.
+    pub fn try_take_shard(&self, n: usize) -> Option<Shard<'vec, T>> {
+        let start = self
+            .next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                next.checked_add(n).filter(|&end| end <= self.end)
+            })
+            .ok()?;
+        trace::shard_taken(&(start..start + n));
+        Some(Shard {
+            storage: self.storage.get(),
+            writer_id: self.writer_id,
+            start_offset: start,
+            end_offset: start + n,
+            initialised_up_to: start,
+            patches: Vec::new(),
+            published: None,
+            progress: self.progress.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Takes up to `n` elements of the vector, fewer if less than `n` remain. Returns None once all
+    /// capacity has been taken. Useful for workers that take fixed-size chunks until there's no
+    /// more work.
+    pub fn take_up_to(&self, n: usize) -> Option<Shard<'vec, T>> {
+        loop {
+            let remaining = self.remaining_capacity();
+            if remaining == 0 {
+                return None;
+            }
+            // Another thread might take capacity between us checking and taking, in which case we
+            // try again.
+            if let Some(shard) = self.try_take_shard(n.min(remaining)) {
+                return Some(shard);
+            }
+        }
+    }
+
+    /// Returns the number of elements that haven't yet been taken.
+    pub fn remaining_capacity(&self) -> usize {
+        self.end - self.next.load(Ordering::Relaxed)
+    }
+
+    /// Returns a shard to the writer. As for `VecWriter::return_shard`, shards can be returned in
+    /// any order. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&self, shard: Shard<T>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&self, shard: Shard<T>) -> Result<(), InitError> {
+        self.writer.lock().unwrap().try_return_shard(shard)
+    }
+}
+
+impl<T, S: ShardableStorage<T>> Drop for SharedVecWriter<'_, '_, T, S> {
+    fn drop(&mut self) {
+        let next = *self.next.get_mut();
+        let writer = self.writer.get_mut().unwrap_or_else(|e| e.into_inner());
+        writer.taken = next;
+        if next > self.start {
+            if let Some(check) = writer.finish_check.as_mut() {
+                check.shard_taken();
+            }
+        }
+        writer.join_back_if_met();
+    }
+}
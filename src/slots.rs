@@ -0,0 +1,106 @@
+//! Distributing individual elements of a shard, for when work is assigned per output index rather
+//! than per contiguous range.
+
+use crate::InitError;
+use crate::Shard;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Holds a shard whose unwritten elements have been handed out as `Slot`s. Once every slot has
+/// been written, `into_shard` gives back the shard, which can then be returned to the writer.
+/// Dropping the set without calling `into_shard` leaks any values written via slots.
+pub struct SlotSet<'vec, T> {
+    shard: Shard<'vec, T>,
+    written: Arc<[AtomicBool]>,
+}
+
+/// A single element of a shard that must be written exactly once. Writing consumes the slot.
+pub struct Slot<'vec, T> {
+    ptr: *mut T,
+    offset: usize,
+
+    /// Flags for all slots of the set, indexed relative to the first slot.
+    written: Arc<[AtomicBool]>,
+
+    index: usize,
+
+    _phantom: PhantomData<&'vec mut T>,
+}
+
+unsafe impl<T: Send> Send for Slot<'_, T> {}
+unsafe impl<T: Sync> Sync for Slot<'_, T> {}
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Splits the part of the shard that hasn't yet been written into one slot per element. The
+    /// slots can be written in any order and from any thread.
+    pub fn into_slots(self) -> (SlotSet<'vec, T>, Vec<Slot<'vec, T>>) {
+        let first = self.initialised_up_to;
+        let written: Arc<[AtomicBool]> = (first..self.end_offset)
+            .map(|_| AtomicBool::new(false))
+            .collect();
+        let slots = (first..self.end_offset)
+            .enumerate()
+            .map(|(index, offset)| Slot {
+                // Safety: The offset is within the shard, so within the storage.
+                ptr: unsafe { self.storage.add(offset) },
+                offset,
+                written: written.clone(),
+                index,
+                _phantom: PhantomData,
+            })
+            .collect();
+        (
+            SlotSet {
+                shard: self,
+                written,
+            },
+            slots,
+        )
+    }
+}
+
+impl<T> Slot<'_, T> {
+    /// Returns the offset in the output vector of the element that this slot will write.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Writes the element.
+    pub fn write(self, value: T) {
+        // Safety: Each slot points to a different element and is consumed by writing, so nothing
+        // else writes to this element.
+        unsafe { self.ptr.write(value) };
+        self.written[self.index].store(true, Ordering::Release);
+    }
+}
+
+impl<'vec, T> SlotSet<'vec, T> {
+    /// Converts back into a shard, which will be full. Fails if any slot hasn't been written, e.g.
+    /// because it was dropped or is still outstanding. On failure, the values written via slots
+    /// are dropped, as for dropping the shard.
+    pub fn into_shard(mut self) -> Result<Shard<'vec, T>, InitError> {
+        let uninit = self
+            .written
+            .iter()
+            .filter(|written| !written.load(Ordering::Acquire))
+            .count();
+        if uninit > 0 {
+            for (index, written) in self.written.iter().enumerate() {
+                if written.load(Ordering::Acquire) {
+                    let offset = self.shard.initialised_up_to + index;
+                    // Safety: The slot for this element was written and has been consumed.
+                    unsafe { self.shard.storage.add(offset).drop_in_place() };
+                }
+            }
+            return Err(InitError::UninitElements {
+                range: self.shard.range(),
+                uninit,
+            });
+        }
+        let written = self.shard.remaining_capacity();
+        self.shard.mark_written(written);
+        Ok(self.shard)
+    }
+}
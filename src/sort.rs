@@ -0,0 +1,106 @@
+use crate::parallel::available_threads;
+use crate::parallel::for_each_in_parallel;
+use crate::parallel::map_in_parallel;
+use crate::parallel::SendPtr;
+use crate::VecWriter;
+
+/// Stable-sorts `input` and appends the result to `output`. Chunks of `input` are sorted in place
+/// on separate threads, then the sorted chunks are split into independent ranges of values, each
+/// of which is merged by a separate thread directly into its own shard of `output`. No temporary
+/// storage other than `input` and `output` is needed.
+pub fn par_sort_into<T: Ord + Send + Sync>(mut input: Vec<T>, output: &mut Vec<T>) {
+    let len = input.len();
+    let num_chunks = available_threads().min(len).max(1);
+    let chunk_size = len.div_ceil(num_chunks).max(1);
+    for_each_in_parallel(input.chunks_mut(chunk_size).collect(), <[T]>::sort);
+
+    // For each output range, find where it starts and ends in each chunk.
+    let chunks: Vec<&[T]> = input.chunks(chunk_size).collect();
+    let pivots = choose_pivots(&chunks, num_chunks);
+    let split_points: Vec<Vec<usize>> = chunks
+        .iter()
+        .map(|chunk| {
+            let mut points: Vec<usize> = std::iter::once(0)
+                .chain(
+                    pivots
+                        .iter()
+                        .map(|pivot| chunk.partition_point(|value| value < *pivot)),
+                )
+                .chain(std::iter::once(chunk.len()))
+                .collect();
+            // An inconsistent `Ord` can give split points that go backwards, which would make
+            // segments overlap and values be moved out twice, so we force them to be monotonic.
+            for i in 1..points.len() {
+                points[i] = points[i].max(points[i - 1]);
+            }
+            points
+        })
+        .collect();
+    let num_segments = pivots.len() + 1;
+
+    output.reserve_exact(len);
+    let mut writer = VecWriter::new(output);
+    let base = SendPtr::new(input.as_mut_ptr());
+    // From here on, values are moved out of `input`, so it must not drop them.
+    // Safety: Setting the length to 0 is always safe. If we panic part-way through merging, values
+    // not yet moved will be leaked.
+    unsafe { input.set_len(0) };
+    let work = (0..num_segments)
+        .map(|segment| {
+            let ranges: Vec<(usize, usize)> = split_points
+                .iter()
+                .enumerate()
+                .map(|(chunk_index, points)| {
+                    let chunk_start = chunk_index * chunk_size;
+                    (
+                        chunk_start + points[segment],
+                        chunk_start + points[segment + 1],
+                    )
+                })
+                .collect();
+            let segment_len = ranges.iter().map(|(start, end)| end - start).sum();
+            (ranges, writer.take_shard(segment_len))
+        })
+        .collect();
+    let shards = map_in_parallel(work, |(mut ranges, mut shard)| {
+        // Safety: Each segment covers a disjoint set of ranges of `input`, which contain values
+        // that haven't yet been moved.
+        let value_at = |offset: usize| unsafe { &*base.get().add(offset) };
+        loop {
+            // Find the smallest head, preferring earlier chunks on ties to keep the sort stable.
+            let mut smallest: Option<usize> = None;
+            for (i, (start, end)) in ranges.iter().enumerate() {
+                if start < end && smallest.is_none_or(|s| value_at(*start) < value_at(ranges[s].0))
+                {
+                    smallest = Some(i);
+                }
+            }
+            let Some(smallest) = smallest else {
+                break;
+            };
+            let offset = ranges[smallest].0;
+            ranges[smallest].0 += 1;
+            // Safety: We advanced past `offset`, so this value won't be read again.
+            shard.push(unsafe { base.get().add(offset).read() });
+        }
+        shard
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+}
+
+/// Chooses `num_segments - 1` values that split the values in `chunks` into roughly equal-sized
+/// segments. Each chunk must be sorted.
+fn choose_pivots<'a, T: Ord>(chunks: &[&'a [T]], num_segments: usize) -> Vec<&'a T> {
+    let mut samples: Vec<&T> = chunks
+        .iter()
+        .flat_map(|chunk| {
+            (0..num_segments).filter_map(|i| chunk.get(i * chunk.len() / num_segments))
+        })
+        .collect();
+    samples.sort();
+    (1..num_segments)
+        .filter_map(|i| samples.get(i * samples.len() / num_segments).copied())
+        .collect()
+}
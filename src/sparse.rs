@@ -0,0 +1,128 @@
+//! Writing the elements of a shard in arbitrary order from a single thread.
+
+use crate::InitError;
+use crate::Shard;
+use std::ops::Range;
+
+/// A shard whose elements can be written in any order, e.g. when partitioning values whose
+/// destination indexes are known but don't arrive sequentially. Which elements have been written is
+/// tracked with a bitmap, so for zero-sized types, avoid converting shards that cover all of a
+/// vec's capacity. Created by `Shard::into_sparse`. Dropping a sparse shard drops any values
+/// written to it.
+pub struct SparseShard<'vec, T> {
+    /// Only None once `into_shard` has taken it.
+    shard: Option<Shard<'vec, T>>,
+
+    /// The part of the output that can be written, i.e. what hadn't been written when the shard
+    /// was converted.
+    range: Range<usize>,
+
+    /// One bit per element of `range`, set if the element has been written.
+    written: Vec<u64>,
+
+    /// The number of bits set in `written`.
+    num_written: usize,
+}
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Converts the part of the shard that hasn't yet been written into a sparse shard, whose
+    /// elements can be written in any order.
+    pub fn into_sparse(self) -> SparseShard<'vec, T> {
+        let range = self.initialised_up_to..self.end_offset;
+        SparseShard {
+            written: vec![0; range.len().div_ceil(64)],
+            range,
+            shard: Some(self),
+            num_written: 0,
+        }
+    }
+}
+
+impl<'vec, T> SparseShard<'vec, T> {
+    /// Writes the element at `offset` in the output vector. If the element was already written,
+    /// then the previous value is dropped. Panics if `offset` is outside the writable range.
+    #[track_caller]
+    pub fn write(&mut self, offset: usize, value: T) {
+        assert!(
+            self.range.contains(&offset),
+            "Offset {offset} is outside the writable range {:?}",
+            self.range
+        );
+        let ptr = self.ptr(offset);
+        let (word, bit) = self.bit(offset);
+        if self.written[word] & bit != 0 {
+            // Safety: The element was written and nothing else references it.
+            unsafe { *ptr = value };
+            return;
+        }
+        // Safety: The element is within our shard and isn't initialised, so there's nothing to
+        // drop.
+        unsafe { ptr.write(value) };
+        self.written[word] |= bit;
+        self.num_written += 1;
+    }
+
+    /// Returns whether the element at `offset` in the output vector has been written.
+    pub fn is_written(&self, offset: usize) -> bool {
+        if !self.range.contains(&offset) {
+            return false;
+        }
+        let (word, bit) = self.bit(offset);
+        self.written[word] & bit != 0
+    }
+
+    /// Returns the range of the output vector that can be written.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Returns the number of elements that haven't yet been written.
+    pub fn remaining(&self) -> usize {
+        self.range.len() - self.num_written
+    }
+
+    /// Converts back into a shard, which will be full. Fails if any element hasn't been written,
+    /// in which case the values that were written are dropped, as for dropping the sparse shard.
+    pub fn into_shard(mut self) -> Result<Shard<'vec, T>, InitError> {
+        let uninit = self.remaining();
+        if uninit > 0 {
+            return Err(InitError::UninitElements {
+                range: self.shard().range(),
+                uninit,
+            });
+        }
+        self.num_written = 0;
+        let mut shard = self.shard.take().unwrap();
+        shard.mark_written(self.range.len());
+        Ok(shard)
+    }
+
+    fn shard(&self) -> &Shard<'vec, T> {
+        self.shard.as_ref().unwrap()
+    }
+
+    fn ptr(&self, offset: usize) -> *mut T {
+        // Safety: Our caller checked that `offset` is within our shard.
+        unsafe { self.shard().storage.add(offset) }
+    }
+
+    /// Returns the index of the word containing the bit for `offset` and a mask for that bit.
+    fn bit(&self, offset: usize) -> (usize, u64) {
+        let index = offset - self.range.start;
+        (index / 64, 1 << (index % 64))
+    }
+}
+
+impl<T> Drop for SparseShard<'_, T> {
+    fn drop(&mut self) {
+        if self.num_written == 0 {
+            return;
+        }
+        for offset in self.range.clone() {
+            if self.is_written(offset) {
+                // Safety: The element was written and is no longer referenced.
+                unsafe { self.ptr(offset).drop_in_place() };
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::Shard;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Lets idle threads ask a busy worker to give up the unwritten tail of the shard that it's
+/// filling. The worker must cooperate by periodically calling `check_in`.
+#[derive(Clone, Default)]
+pub struct StealRequest {
+    requested: Arc<AtomicBool>,
+}
+
+impl<'vec, T> Shard<'vec, T> {
+    /// Splits the shard into two at `n` elements from its start. The shard may have been partially
+    /// written, in which case any values written after the split point belong to the second shard.
+    /// Both shards can be returned to the original writer. Panics if `n` is greater than the size
+    /// of the shard.
+    #[track_caller]
+    pub fn split_at(mut self, n: usize) -> (Shard<'vec, T>, Shard<'vec, T>) {
+        let tail = self.split_off(n);
+        (self, tail)
+    }
+
+    /// Splits off the elements from `n` onwards into a new shard, leaving this shard with the
+    /// first `n` elements. As for `split_at`, the shard may have been partially written. Panics if
+    /// `n` is greater than the size of the shard.
+    #[track_caller]
+    pub fn split_off(&mut self, n: usize) -> Shard<'vec, T> {
+        assert!(
+            n <= self.end_offset - self.start_offset,
+            "Split point {n} is beyond the end of the shard"
+        );
+        let split_offset = self.start_offset + n;
+        let tail = Shard {
+            storage: self.storage,
+            writer_id: self.writer_id,
+            start_offset: split_offset,
+            end_offset: self.end_offset,
+            initialised_up_to: self.initialised_up_to.max(split_offset),
+            patches: Vec::new(),
+            // Published views may cover values that are now in the tail, so it mustn't drop them
+            // while they're still in use. Neither half holds the views' token, so once they're
+            // dropped, both halves can be returned.
+            published: self.published.clone(),
+            progress: self.progress.clone(),
+            _phantom: Default::default(),
+        };
+        self.end_offset = split_offset;
+        self.initialised_up_to = self.initialised_up_to.min(split_offset);
+        tail
+    }
+
+    /// Splits the last `n` elements off this shard into a new shard, which can be filled by another
+    /// thread. None of the split off elements may have been written yet. Both shards must be
+    /// returned. Returns None if fewer than `n` elements remain unwritten.
+    pub fn split_off_tail(&mut self, n: usize) -> Option<Shard<'vec, T>> {
+        if self.remaining_capacity() < n {
+            return None;
+        }
+        Some(self.split_off(self.end_offset - self.start_offset - n))
+    }
+
+    /// Splits the shard into sub-shards of `size` elements, as for `slice::chunks_mut`, except
+    /// that the last may be smaller. This allows one large shard to be divided between many small
+    /// tasks. Values already written go with the sub-shard that covers them and any queued patches
+    /// go with the first sub-shard. All sub-shards can be returned to the original writer. Panics
+    /// if `size` is zero.
+    #[track_caller]
+    pub fn chunks(self, size: usize) -> impl Iterator<Item = Shard<'vec, T>> {
+        assert!(size != 0, "Chunk size must be non-zero");
+        let mut rest = Some(self).filter(|shard| shard.capacity() > 0);
+        std::iter::from_fn(move || {
+            let mut chunk = rest.take()?;
+            if chunk.capacity() > size {
+                rest = Some(chunk.split_off(size));
+            }
+            Some(chunk)
+        })
+    }
+}
+
+impl StealRequest {
+    /// Creates a new request handle. Nothing is requested until `request` is called.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks whichever worker next checks in to split off part of its shard.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Called by a worker while filling `shard`. If a steal has been requested and at least two
+    /// elements of `shard` remain unwritten, then the request is cleared and the unwritten
+    /// elements are split in half, with the second half returned for the worker to hand to an
+    /// idle thread.
+    pub fn check_in<'vec, T>(&self, shard: &mut Shard<'vec, T>) -> Option<Shard<'vec, T>> {
+        let remaining = shard.end_offset - shard.initialised_up_to;
+        if remaining < 2 || !self.requested.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        shard.split_off_tail(remaining / 2)
+    }
+}
@@ -0,0 +1,177 @@
+#[cfg(feature = "allocator_api")]
+use std::alloc::Allocator;
+use std::mem::MaybeUninit;
+
+/// A contiguous buffer that a `VecWriter` can initialise via shards. The buffer consists of an
+/// initialised prefix of `len` elements followed by uninitialised space up to `capacity`.
+///
+/// # Safety
+///
+/// Implementations must ensure that `as_mut_ptr` points to space for `capacity` elements, that the
+/// first `len` elements are initialised and that neither the pointer nor the capacity change while
+/// the storage is mutably borrowed by a writer, other than via `set_len`.
+pub unsafe trait ShardableStorage<T> {
+    /// Returns a pointer to the start of the buffer.
+    fn as_ptr(&self) -> *const T;
+
+    /// Returns a mutable pointer to the start of the buffer.
+    fn as_mut_ptr(&mut self) -> *mut T;
+
+    /// Returns the number of elements that the buffer can hold.
+    fn capacity(&self) -> usize;
+
+    /// Returns the number of elements at the start of the buffer that are initialised.
+    fn len(&self) -> usize;
+
+    /// Returns whether no elements are initialised.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets the number of elements at the start of the buffer that are initialised.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not exceed the capacity and all elements up to `len` must be initialised.
+    unsafe fn set_len(&mut self, len: usize);
+}
+
+// Safety: `Vec` upholds all the requirements, provided it isn't reallocated, which can't happen
+// while it's mutably borrowed.
+#[cfg(not(feature = "allocator_api"))]
+unsafe impl<T> ShardableStorage<T> for Vec<T> {
+    fn as_ptr(&self) -> *const T {
+        Vec::as_ptr(self)
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        Vec::as_mut_ptr(self)
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        unsafe { Vec::set_len(self, len) }
+    }
+}
+
+// Safety: As above. The allocator doesn't matter, since we never allocate or free.
+#[cfg(feature = "allocator_api")]
+unsafe impl<T, A: Allocator> ShardableStorage<T> for Vec<T, A> {
+    fn as_ptr(&self) -> *const T {
+        Vec::as_ptr(self)
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        Vec::as_mut_ptr(self)
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        unsafe { Vec::set_len(self, len) }
+    }
+}
+
+/// Storage backed by a borrowed slice of possibly uninitialised elements, such as the contents of a
+/// `Box<[MaybeUninit<T>]>` or of a memory-mapped file. Values written into the slice aren't
+/// dropped when the storage is dropped. Use `len` to find out how many elements were initialised.
+pub struct UninitStorage<'a, T> {
+    slice: &'a mut [MaybeUninit<T>],
+    len: usize,
+}
+
+impl<'a, T> UninitStorage<'a, T> {
+    /// Creates storage over `slice`, none of which is initialised.
+    pub fn new(slice: &'a mut [MaybeUninit<T>]) -> Self {
+        Self { slice, len: 0 }
+    }
+
+    /// Returns the number of elements at the start of the slice that have been initialised.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether no elements have been initialised.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the elements that have been initialised.
+    pub fn initialised(&self) -> &[T] {
+        // Safety: The first `len` elements are initialised.
+        unsafe { std::slice::from_raw_parts(self.slice.as_ptr().cast(), self.len) }
+    }
+}
+
+// Safety: The slice's pointer and length can't change while it's borrowed, and we only increase
+// `len` via `set_len`, whose caller promises that the elements are initialised.
+unsafe impl<T> ShardableStorage<T> for UninitStorage<'_, T> {
+    fn as_ptr(&self) -> *const T {
+        self.slice.as_ptr().cast()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.slice.as_mut_ptr().cast()
+    }
+
+    fn capacity(&self) -> usize {
+        self.slice.len()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        self.len = len;
+    }
+}
+
+// Safety: `BytesMut` doesn't reallocate unless asked to reserve space, which can't happen while
+// it's mutably borrowed. Its pointer is to the start of its buffer, even when its length is zero.
+// Shards write into its spare capacity, so the pointer returned by `as_mut_ptr` is derived from a
+// slice that covers the whole buffer, not just the initialised part that `DerefMut` gives access
+// to.
+#[cfg(feature = "bytes")]
+unsafe impl ShardableStorage<u8> for bytes::BytesMut {
+    fn as_ptr(&self) -> *const u8 {
+        <[u8]>::as_ptr(self)
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        let len = bytes::BytesMut::len(self);
+        // Safety: With a length of zero, the spare capacity is the whole buffer. Restoring the
+        // length afterwards is fine, since we didn't touch the bytes in between.
+        unsafe {
+            bytes::BytesMut::set_len(self, 0);
+            let ptr = self.spare_capacity_mut().as_mut_ptr().cast();
+            bytes::BytesMut::set_len(self, len);
+            ptr
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        bytes::BytesMut::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        bytes::BytesMut::len(self)
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        unsafe { bytes::BytesMut::set_len(self, len) }
+    }
+}
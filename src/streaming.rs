@@ -0,0 +1,109 @@
+//! Writing with non-temporal stores, which bypass the cache, so that filling a huge output that
+//! won't be read soon doesn't evict data that concurrently running threads are using.
+
+use crate::InsufficientCapacity;
+use crate::Shard;
+
+/// The number of bytes of repeated values that `fill_non_temporal` copies at a time.
+const FILL_CHUNK_BYTES: usize = 4096;
+
+impl<T: Copy> Shard<'_, T> {
+    /// As for `copy_from_slice`, but on x86_64 writes using non-temporal stores. Elsewhere, this
+    /// is the same as `copy_from_slice`. Only worthwhile for large copies whose output won't be
+    /// read again soon. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn copy_from_slice_non_temporal(&mut self, values: &[T]) {
+        self.try_copy_from_slice_non_temporal(values).unwrap();
+    }
+
+    /// As for `copy_from_slice_non_temporal`, but returns an error rather than panicking if there
+    /// is insufficient capacity. Nothing is written on failure.
+    pub fn try_copy_from_slice_non_temporal(
+        &mut self,
+        values: &[T],
+    ) -> Result<(), InsufficientCapacity> {
+        if self.remaining_capacity() < values.len() {
+            return Err(InsufficientCapacity);
+        }
+        // Safety: We checked that the values fit within the shard, which doesn't alias anything
+        // else. `T: Copy`, so copying its bytes is a valid way to copy it.
+        unsafe {
+            stream_copy(
+                values.as_ptr().cast(),
+                self.storage.add(self.initialised_up_to).cast(),
+                size_of_val(values),
+            );
+            store_fence();
+        }
+        self.mark_written(values.len());
+        Ok(())
+    }
+
+    /// As for `fill`, but on x86_64 writes using non-temporal stores. Elsewhere, this is the same
+    /// as `fill`.
+    pub fn fill_non_temporal(&mut self, value: T) {
+        if size_of::<T>() == 0 || self.remaining_capacity() == 0 {
+            self.fill(value);
+            return;
+        }
+        let chunk =
+            vec![value; (FILL_CHUNK_BYTES / size_of::<T>()).clamp(1, self.remaining_capacity())];
+        while self.remaining_capacity() > 0 {
+            let n = chunk.len().min(self.remaining_capacity());
+            // Safety: `n` is within the remaining capacity of the shard.
+            unsafe {
+                stream_copy(
+                    chunk.as_ptr().cast(),
+                    self.storage.add(self.initialised_up_to).cast(),
+                    n * size_of::<T>(),
+                );
+            }
+            self.mark_written(n);
+        }
+        // Safety: This just orders the stores above before any that follow.
+        unsafe { store_fence() };
+    }
+}
+
+/// Copies `len` bytes from `src` to `dst` using non-temporal stores where `dst` is suitably
+/// aligned. `store_fence` must be called before another thread reads what was written.
+///
+/// # Safety
+///
+/// As for `std::ptr::copy_nonoverlapping`.
+#[cfg(target_arch = "x86_64")]
+unsafe fn stream_copy(src: *const u8, dst: *mut u8, len: usize) {
+    use std::arch::x86_64::__m128i;
+    use std::arch::x86_64::_mm_loadu_si128;
+    use std::arch::x86_64::_mm_stream_si128;
+
+    // Non-temporal stores need a 16 byte aligned destination, so copy up to the first aligned
+    // address normally.
+    let head = dst.align_offset(16).min(len);
+    std::ptr::copy_nonoverlapping(src, dst, head);
+    let mut offset = head;
+    while offset + 16 <= len {
+        let value = _mm_loadu_si128(src.add(offset).cast::<__m128i>());
+        _mm_stream_si128(dst.add(offset).cast::<__m128i>(), value);
+        offset += 16;
+    }
+    std::ptr::copy_nonoverlapping(src.add(offset), dst.add(offset), len - offset);
+}
+
+/// Makes stores done by `stream_copy` visible to other threads before any later stores.
+///
+/// # Safety
+///
+/// Always safe to call. It's unsafe only for consistency with the platforms that need it.
+#[cfg(target_arch = "x86_64")]
+unsafe fn store_fence() {
+    std::arch::x86_64::_mm_sfence();
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn stream_copy(src: *const u8, dst: *mut u8, len: usize) {
+    std::ptr::copy_nonoverlapping(src, dst, len);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn store_fence() {}
@@ -0,0 +1,92 @@
+use crate::parallel::available_threads;
+use crate::parallel::map_in_parallel;
+use crate::VecWriter;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// A table of null-terminated strings, such as an ELF string table, built by `par_string_table`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringTable<'a> {
+    /// The contents of the table.
+    pub bytes: Vec<u8>,
+
+    /// The offset in `bytes` of each distinct string.
+    pub offsets: HashMap<&'a str, usize>,
+}
+
+/// Builds a string table containing each distinct string in `strings` once, followed by a null
+/// byte, in order of first occurrence. Strings are first hashed and partitioned into buckets in
+/// parallel, with each thread handling a chunk of the input. Each bucket is then deduplicated by
+/// its own thread. The bytes of the strings are then copied into the table in parallel, with each
+/// thread writing its own shard of the table.
+pub fn par_string_table<'a>(strings: &[&'a str]) -> StringTable<'a> {
+    let num_buckets = available_threads().min(strings.len()).max(1);
+    let hasher = RandomState::new();
+    let bucket_of = |string: &str| (hasher.hash_one(string) % num_buckets as u64) as usize;
+
+    // Partition each chunk of the input into buckets, keeping the index of each string.
+    let chunk_size = strings.len().div_ceil(num_buckets).max(1);
+    let partitioned = map_in_parallel(
+        strings.chunks(chunk_size).enumerate().collect(),
+        |(chunk_index, chunk)| {
+            let mut buckets: Vec<Vec<(usize, &'a str)>> = vec![Vec::new(); num_buckets];
+            for (i, &string) in chunk.iter().enumerate() {
+                buckets[bucket_of(string)].push((chunk_index * chunk_size + i, string));
+            }
+            buckets
+        },
+    );
+    let mut bucket_inputs: Vec<Vec<Vec<(usize, &'a str)>>> = vec![Vec::new(); num_buckets];
+    for chunk_buckets in partitioned {
+        for (bucket, strings) in chunk_buckets.into_iter().enumerate() {
+            bucket_inputs[bucket].push(strings);
+        }
+    }
+
+    // Each bucket keeps the first occurrence of each of its strings. Chunks are in input order, so
+    // the first time we see a string is its first occurrence.
+    let buckets = map_in_parallel(bucket_inputs, |chunks| {
+        let mut first_occurrences: HashMap<&'a str, usize> = HashMap::new();
+        for (index, string) in chunks.into_iter().flatten() {
+            first_occurrences.entry(string).or_insert(index);
+        }
+        first_occurrences
+    });
+    let mut unique: Vec<(usize, &'a str)> = buckets
+        .into_iter()
+        .flat_map(|bucket| bucket.into_iter().map(|(string, index)| (index, string)))
+        .collect();
+    unique.sort_unstable_by_key(|(index, _)| *index);
+
+    let mut offsets = HashMap::with_capacity(unique.len());
+    let mut total = 0;
+    for (_, string) in &unique {
+        offsets.insert(*string, total);
+        total += string.len() + 1;
+    }
+
+    let mut bytes = Vec::with_capacity(total);
+    let mut writer = VecWriter::new(&mut bytes);
+    let group_size = unique.len().div_ceil(available_threads()).max(1);
+    let work = unique
+        .chunks(group_size)
+        .map(|group| {
+            let size = group.iter().map(|(_, string)| string.len() + 1).sum();
+            (group, writer.take_shard(size))
+        })
+        .collect();
+    let shards = map_in_parallel(work, |(group, mut shard)| {
+        for (_, string) in group {
+            for &byte in string.as_bytes() {
+                shard.push(byte);
+            }
+            shard.push(0);
+        }
+        shard
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    StringTable { bytes, offsets }
+}
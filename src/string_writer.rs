@@ -0,0 +1,93 @@
+use crate::InitError;
+use crate::InsufficientCapacity;
+use crate::Shard;
+use crate::VecWriter;
+use std::fmt;
+
+/// Builds a `String`, with each part of the string being written separately, most likely from a
+/// separate thread. Shards only accept whole `str`s and `char`s, so the string is always valid
+/// UTF-8. Sizes and offsets are in bytes.
+pub struct ShardedStringWriter<'s> {
+    writer: VecWriter<'s, u8>,
+}
+
+/// A mutable borrow of part of a `String`, taken from a `ShardedStringWriter`.
+pub struct StringShard<'s> {
+    shard: Shard<'s, u8>,
+}
+
+impl<'s> ShardedStringWriter<'s> {
+    /// Creates a new writer that will write into the supplied `String`.
+    pub fn new(string: &'s mut String) -> Self {
+        // Safety: Only whole `str`s and `char`s can be written to shards and shards must be fully
+        // written before they're returned, so the initialised part of the vec is always valid
+        // UTF-8.
+        let vec = unsafe { string.as_mut_vec() };
+        Self {
+            writer: VecWriter::new(vec),
+        }
+    }
+
+    /// Takes the next `n` bytes of the string or panics if there is insufficient capacity.
+    pub fn take_shard(&mut self, n: usize) -> StringShard<'s> {
+        StringShard {
+            shard: self.writer.take_shard(n),
+        }
+    }
+
+    /// Takes the next `n` bytes of the string or returns None if there is insufficient capacity.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<StringShard<'s>> {
+        Some(StringShard {
+            shard: self.writer.try_take_shard(n)?,
+        })
+    }
+
+    /// Returns a shard to the string. The shard must have been completely filled. Panics on
+    /// failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: StringShard) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, shard: StringShard) -> Result<(), InitError> {
+        self.writer.try_return_shard(shard.shard)
+    }
+}
+
+impl StringShard<'_> {
+    /// Appends `s` to the shard. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn push_str(&mut self, s: &str) {
+        self.try_push_str(s).unwrap();
+    }
+
+    /// As for `push_str`, but returns an error rather than panicking if there is insufficient
+    /// capacity. Nothing is written on failure.
+    pub fn try_push_str(&mut self, s: &str) -> Result<(), InsufficientCapacity> {
+        self.shard.try_copy_from_slice(s.as_bytes())
+    }
+
+    /// Appends `c` to the shard. Panics if there is insufficient capacity.
+    #[track_caller]
+    pub fn push(&mut self, c: char) {
+        self.try_push(c).unwrap();
+    }
+
+    /// As for `push`, but returns an error rather than panicking if there is insufficient capacity.
+    pub fn try_push(&mut self, c: char) -> Result<(), InsufficientCapacity> {
+        self.try_push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Returns the number of bytes that can still be written to the shard.
+    pub fn remaining(&self) -> usize {
+        self.shard.remaining_capacity()
+    }
+}
+
+/// Fails with `fmt::Error` if there is insufficient capacity.
+impl fmt::Write for StringShard<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.try_push_str(s).map_err(|_| fmt::Error)
+    }
+}
@@ -0,0 +1,122 @@
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::ops::Range;
+
+/// A writer whose shards each carry a summary value, e.g. the minimum and maximum of the values
+/// written, that the worker updates as it writes. The summaries are collected as shards are
+/// returned.
+pub struct SummaryWriter<'vec, T, S> {
+    writer: VecWriter<'vec, T>,
+    summaries: Vec<ShardSummary<S>>,
+}
+
+/// A shard together with a summary of its contents.
+pub struct SummaryShard<'vec, T, S> {
+    shard: Shard<'vec, T>,
+    summary: S,
+}
+
+/// The summary of a shard that has been returned, together with the range of the output vector
+/// that the shard covered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardSummary<S> {
+    pub range: Range<usize>,
+    pub summary: S,
+}
+
+impl<'vec, T, S> SummaryWriter<'vec, T, S> {
+    /// Creates a new writer that will write into the supplied `Vec`.
+    pub fn new(storage: &'vec mut Vec<T>) -> Self {
+        Self {
+            writer: VecWriter::new(storage),
+            summaries: Vec::new(),
+        }
+    }
+
+    /// Takes the next `n` elements of the vector, with `initial` as the shard's summary. Panics if
+    /// there is insufficient capacity.
+    pub fn take_shard(&mut self, n: usize, initial: S) -> SummaryShard<'vec, T, S> {
+        SummaryShard {
+            shard: self.writer.take_shard(n),
+            summary: initial,
+        }
+    }
+
+    /// As for `take_shard`, but returns None if there is insufficient capacity.
+    pub fn try_take_shard(&mut self, n: usize, initial: S) -> Option<SummaryShard<'vec, T, S>> {
+        Some(SummaryShard {
+            shard: self.writer.try_take_shard(n)?,
+            summary: initial,
+        })
+    }
+
+    /// Returns a shard to the vector and records its summary. See `VecWriter::return_shard`.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: SummaryShard<T, S>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking. On failure,
+    /// the shard's summary is discarded.
+    pub fn try_return_shard(&mut self, shard: SummaryShard<T, S>) -> Result<(), InitError> {
+        let range = shard.shard.start_offset..shard.shard.end_offset;
+        self.writer.try_return_shard(shard.shard)?;
+        self.summaries.push(ShardSummary {
+            range,
+            summary: shard.summary,
+        });
+        Ok(())
+    }
+
+    /// Checks that all shards that were taken have been returned, then returns the summaries of
+    /// the shards in the order of their ranges.
+    pub fn finish(mut self) -> Result<Vec<ShardSummary<S>>, InitError> {
+        if !self.writer.all_returned() {
+            return Err(self.writer.missing_shard_error());
+        }
+        self.summaries.sort_by_key(|summary| summary.range.start);
+        Ok(self.summaries)
+    }
+}
+
+impl<T, S> SummaryShard<'_, T, S> {
+    /// Returns the shard's summary.
+    pub fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    /// Returns the shard's summary for updating.
+    pub fn summary_mut(&mut self) -> &mut S {
+        &mut self.summary
+    }
+
+    /// Appends a value to the shard, first calling `fold` to update the shard's summary with the
+    /// value. Panics if the shard has already been fully used, in which case the summary is
+    /// unchanged.
+    #[track_caller]
+    pub fn push_folded(&mut self, value: T, fold: impl FnOnce(&mut S, &T)) {
+        assert!(
+            self.shard.initialised_up_to < self.shard.end_offset,
+            "Shard is full"
+        );
+        fold(&mut self.summary, &value);
+        self.shard.push(value);
+    }
+}
+
+impl<'vec, T, S> Deref for SummaryShard<'vec, T, S> {
+    type Target = Shard<'vec, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shard
+    }
+}
+
+impl<T, S> DerefMut for SummaryShard<'_, T, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shard
+    }
+}
@@ -0,0 +1,101 @@
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+/// A shard that carries a user-supplied tag, e.g. identifying the task that the shard is for. The
+/// tag is handed back when the shard is returned and is included in any error.
+pub struct TaggedShard<'vec, T, G> {
+    shard: Shard<'vec, T>,
+    tag: G,
+}
+
+impl<'vec, T> VecWriter<'vec, T> {
+    /// Takes the next `n` elements of the vector as a shard with the specified tag. Panics if there
+    /// is insufficient capacity.
+    pub fn take_tagged_shard<G>(&mut self, n: usize, tag: G) -> TaggedShard<'vec, T, G> {
+        TaggedShard {
+            shard: self.take_shard(n),
+            tag,
+        }
+    }
+
+    /// As for `take_tagged_shard`, but returns None if there is insufficient capacity.
+    pub fn try_take_tagged_shard<G>(
+        &mut self,
+        n: usize,
+        tag: G,
+    ) -> Option<TaggedShard<'vec, T, G>> {
+        Some(TaggedShard {
+            shard: self.try_take_shard(n)?,
+            tag,
+        })
+    }
+
+    /// Returns a tagged shard to the vector, handing back its tag. See `return_shard`.
+    #[track_caller]
+    pub fn return_tagged_shard<G: Debug>(&mut self, shard: TaggedShard<T, G>) -> G {
+        self.try_return_tagged_shard(shard).unwrap()
+    }
+
+    /// As for `return_tagged_shard`, but returns an error containing the tag on failure rather
+    /// than panicking.
+    pub fn try_return_tagged_shard<G>(
+        &mut self,
+        shard: TaggedShard<T, G>,
+    ) -> Result<G, TaggedInitError<G>> {
+        let TaggedShard { shard, tag } = shard;
+        match self.try_return_shard(shard) {
+            Ok(()) => Ok(tag),
+            Err(error) => Err(TaggedInitError { tag, error }),
+        }
+    }
+}
+
+impl<'vec, T, G> TaggedShard<'vec, T, G> {
+    /// Returns the shard's tag.
+    pub fn tag(&self) -> &G {
+        &self.tag
+    }
+
+    /// Splits this into a plain shard and its tag.
+    pub fn into_parts(self) -> (Shard<'vec, T>, G) {
+        (self.shard, self.tag)
+    }
+
+    /// Converts this back into a plain shard, discarding the tag.
+    pub fn into_shard(self) -> Shard<'vec, T> {
+        self.shard
+    }
+}
+
+impl<'vec, T, G> Deref for TaggedShard<'vec, T, G> {
+    type Target = Shard<'vec, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shard
+    }
+}
+
+impl<T, G> DerefMut for TaggedShard<'_, T, G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shard
+    }
+}
+
+/// An error returning a tagged shard, together with the shard's tag.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TaggedInitError<G> {
+    pub tag: G,
+    pub error: InitError,
+}
+impl<G: Debug> Error for TaggedInitError<G> {}
+impl<G: Debug> Display for TaggedInitError<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (shard {:?})", self.error, self.tag)
+    }
+}
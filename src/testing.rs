@@ -0,0 +1,124 @@
+//! Support for testing code that uses this crate.
+
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// A wrapper around `VecWriter` that can be configured to inject failures, so that error handling
+/// in code that uses this crate can be tested deterministically. Shards are identified by the order
+/// in which they're taken, starting from 0. Failed takes also count.
+pub struct TestWriter<'vec, T> {
+    writer: VecWriter<'vec, T>,
+
+    /// The number of calls to take a shard so far.
+    takes: usize,
+
+    failed_takes: HashSet<usize>,
+
+    /// Shard index and the number of values that can be pushed before the shard reports
+    /// insufficient capacity.
+    push_limits: Vec<(usize, usize)>,
+
+    lost_shards: HashSet<usize>,
+
+    /// Maps start offsets of shards whose capacity was limited to their original end offsets.
+    limited_starts: HashMap<usize, usize>,
+
+    /// Start offsets of shards that will be lost when returned.
+    lost_starts: HashSet<usize>,
+}
+
+impl<'vec, T> TestWriter<'vec, T> {
+    /// Creates a new writer that will write into the supplied `Vec`. Until configured otherwise,
+    /// it behaves the same as a `VecWriter`.
+    pub fn new(storage: &'vec mut Vec<T>) -> Self {
+        Self {
+            writer: VecWriter::new(storage),
+            takes: 0,
+            failed_takes: HashSet::new(),
+            push_limits: Vec::new(),
+            lost_shards: HashSet::new(),
+            limited_starts: HashMap::new(),
+            lost_starts: HashSet::new(),
+        }
+    }
+
+    /// Makes the attempt to take shard `index` fail as if there was insufficient capacity.
+    pub fn fail_take(mut self, index: usize) -> Self {
+        self.failed_takes.insert(index);
+        self
+    }
+
+    /// Makes shard `index` report insufficient capacity once `limit` values have been pushed.
+    /// Returning the shard will then fail with `InitError::UninitElements`, as it would if a worker
+    /// stopped early.
+    pub fn limit_pushes(mut self, index: usize, limit: usize) -> Self {
+        self.push_limits.push((index, limit));
+        self
+    }
+
+    /// Makes shard `index` be dropped when it's returned, as if the worker had dropped it instead
+    /// of returning it. The return will appear to succeed.
+    pub fn lose_shard(mut self, index: usize) -> Self {
+        self.lost_shards.insert(index);
+        self
+    }
+
+    /// Takes the next `n` elements of the vector or panics if there is insufficient capacity or a
+    /// failure was injected.
+    pub fn take_shard(&mut self, n: usize) -> Shard<'vec, T> {
+        self.try_take_shard(n)
+            .unwrap_or_else(|| panic!("Failed to take shard of size {n}"))
+    }
+
+    /// Takes the next `n` elements of the vector or returns None if there is insufficient
+    /// capacity or a failure was injected.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<Shard<'vec, T>> {
+        let index = self.takes;
+        self.takes += 1;
+        if self.failed_takes.contains(&index) {
+            return None;
+        }
+        let mut shard = self.writer.try_take_shard(n)?;
+        if let Some(&(_, limit)) = self.push_limits.iter().find(|(i, _)| *i == index) {
+            // A limit that's at least the shard's size doesn't limit anything.
+            if limit < n {
+                self.limited_starts
+                    .insert(shard.start_offset, shard.end_offset);
+                shard.end_offset = shard.start_offset + limit;
+            }
+        }
+        if self.lost_shards.contains(&index) {
+            self.lost_starts.insert(shard.start_offset);
+        }
+        Some(shard)
+    }
+
+    /// Returns a shard to the vector. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: Shard<T>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        if self.lost_starts.remove(&shard.start_offset) {
+            drop(shard);
+            return Ok(());
+        }
+        if let Some(end) = self.limited_starts.remove(&shard.start_offset) {
+            return Err(InitError::UninitElements {
+                range: shard.start_offset..end,
+                uninit: end - shard.initialised_up_to,
+            });
+        }
+        self.writer.try_return_shard(shard)
+    }
+
+    /// Returns the underlying writer, e.g. to call methods that aren't wrapped.
+    pub fn writer(&mut self) -> &mut VecWriter<'vec, T> {
+        &mut self.writer
+    }
+}
@@ -0,0 +1,247 @@
+use crate::InitError;
+use crate::InsufficientCapacity;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Builds an image-like `Vec<T>` made up of `height` rows, each `stride` elements apart, with each
+/// rectangular tile of the image being initialised separately, most likely from a separate thread.
+pub struct TileWriter<'vec, T> {
+    storage: &'vec mut Vec<T>,
+    width: usize,
+    height: usize,
+    stride: usize,
+
+    /// The tiles that have been taken so far. Used to ensure that tiles don't overlap.
+    taken: Vec<Rect>,
+
+    /// The number of elements in tiles that have been returned.
+    returned_area: usize,
+}
+
+/// A rectangular region of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A mutable borrow of a rectangular region of an image. Elements are written row-by-row. Dropping
+/// a tile without returning it to the writer will drop any values that were written into it.
+pub struct Tile<'vec, T> {
+    /// Pointer to the start of `storage` on the writer.
+    storage: *mut T,
+
+    stride: usize,
+
+    rect: Rect,
+
+    /// The number of elements that have been written.
+    written: usize,
+
+    _phantom: PhantomData<&'vec mut T>,
+}
+
+impl<T> Drop for Tile<'_, T> {
+    fn drop(&mut self) {
+        for i in 0..self.written {
+            unsafe { self.storage.add(self.offset_of(i)).read() };
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for Tile<'_, T> {}
+unsafe impl<T: Sync> Sync for Tile<'_, T> {}
+
+impl Rect {
+    fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+impl<'vec, T> TileWriter<'vec, T> {
+    /// Creates a new writer that will write an image of `width` by `height` elements into the
+    /// supplied `Vec`, with each row starting `stride` elements after the previous row. Panics if
+    /// the vec isn't empty, if its capacity is less than `stride * height` or if `stride` is less
+    /// than `width`.
+    pub fn new(storage: &'vec mut Vec<T>, width: usize, height: usize, stride: usize) -> Self {
+        assert!(storage.is_empty(), "TileWriter requires an empty Vec");
+        assert!(
+            stride >= width,
+            "Stride {stride} is less than width {width}"
+        );
+        let required = stride.checked_mul(height).expect("Image size overflowed");
+        assert!(
+            storage.capacity() >= required,
+            "Capacity {} is less than the {required} required",
+            storage.capacity()
+        );
+        Self {
+            storage,
+            width,
+            height,
+            stride,
+            taken: Vec::new(),
+            returned_area: 0,
+        }
+    }
+
+    /// Takes the tile covering `rect`. Panics if `rect` is outside the image or overlaps a
+    /// previously taken tile.
+    pub fn take_tile(&mut self, rect: Rect) -> Tile<'vec, T> {
+        self.try_take_tile(rect)
+            .unwrap_or_else(|| panic!("Tile {rect:?} is out of bounds or overlaps another tile"))
+    }
+
+    /// Takes the tile covering `rect` or returns None if `rect` is outside the image or overlaps a
+    /// previously taken tile.
+    pub fn try_take_tile(&mut self, rect: Rect) -> Option<Tile<'vec, T>> {
+        if rect.x.checked_add(rect.width)? > self.width
+            || rect.y.checked_add(rect.height)? > self.height
+            || self.taken.iter().any(|taken| taken.overlaps(&rect))
+        {
+            return None;
+        }
+        self.taken.push(rect);
+        Some(Tile {
+            storage: self.storage.as_mut_ptr(),
+            stride: self.stride,
+            rect,
+            written: 0,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns a tile to the writer. The tile must have been fully initialised. Tiles can be
+    /// returned in any order. Panics on failure.
+    #[track_caller]
+    pub fn return_tile(&mut self, tile: Tile<T>) {
+        self.try_return_tile(tile).unwrap()
+    }
+
+    /// As for `return_tile`, but returns an error on failure rather than panicking.
+    pub fn try_return_tile(&mut self, tile: Tile<T>) -> Result<(), InitError> {
+        if self.storage.as_mut_ptr() != tile.storage {
+            return Err(InitError::WrongVec {
+                range: tile.offsets(),
+            });
+        }
+        if tile.written != tile.rect.area() {
+            return Err(InitError::UninitElements {
+                range: tile.offsets(),
+                uninit: tile.rect.area() - tile.written,
+            });
+        }
+        self.returned_area += tile.rect.area();
+
+        // The values written into the tile will become owned by the vec when we finish.
+        core::mem::forget(tile);
+        Ok(())
+    }
+
+    /// Checks that tiles covering the whole image have been returned, then fills any padding
+    /// between the end of each row and the stride with `T::default()` and sets the length of the
+    /// vec. If an error is returned, the values in returned tiles are leaked.
+    pub fn finish(self) -> Result<(), InitError>
+    where
+        T: Default,
+    {
+        if self.returned_area != self.width * self.height {
+            return Err(InitError::UninitElements {
+                range: 0..self.height * self.stride,
+                uninit: self.width * self.height - self.returned_area,
+            });
+        }
+        let storage = self.storage.as_mut_ptr();
+        for y in 0..self.height {
+            for x in self.width..self.stride {
+                // Safety: Padding isn't covered by any tile, so nothing else has written here.
+                unsafe { storage.add(y * self.stride + x).write(T::default()) };
+            }
+        }
+        // Safety: Every element is either padding, which we just initialised, or part of a tile
+        // that was fully initialised before being returned.
+        unsafe { self.storage.set_len(self.stride * self.height) };
+        Ok(())
+    }
+}
+
+impl<T> Tile<'_, T> {
+    /// Returns the region of the image covered by this tile.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Returns the image coordinates at which the next push will write, or None if the tile is
+    /// full.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        if self.written == self.rect.area() {
+            return None;
+        }
+        Some((
+            self.rect.x + self.written % self.rect.width,
+            self.rect.y + self.written / self.rect.width,
+        ))
+    }
+
+    /// Appends a value to the tile. Values fill each row of the tile before moving to the next
+    /// row. Panics if the tile has already been fully used.
+    #[track_caller]
+    pub fn push(&mut self, value: T) {
+        self.try_push(value).unwrap();
+    }
+
+    /// Appends a value to the tile or returns an error if it has already been fully used.
+    pub fn try_push(&mut self, value: T) -> Result<(), InsufficientCapacity> {
+        if self.written == self.rect.area() {
+            return Err(InsufficientCapacity);
+        }
+        // Safety: The tile is within the image, which is within the capacity of the vec and
+        // doesn't overlap any other tile.
+        unsafe { self.storage.add(self.offset_of(self.written)).write(value) };
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Appends a row of values to the tile. Panics if `row` isn't the width of the tile or if the
+    /// tile doesn't have room for another row.
+    #[track_caller]
+    pub fn push_row(&mut self, row: &[T])
+    where
+        T: Clone,
+    {
+        assert_eq!(row.len(), self.rect.width, "Row length doesn't match tile");
+        assert_eq!(
+            self.written % self.rect.width.max(1),
+            0,
+            "Tile is part-way through a row"
+        );
+        for value in row {
+            self.push(value.clone());
+        }
+    }
+
+    /// Returns the range of offsets in the vec spanned by the tile, including elements in other
+    /// tiles that are between the tile's rows.
+    fn offsets(&self) -> Range<usize> {
+        match self.rect.area() {
+            0 => 0..0,
+            area => self.offset_of(0)..self.offset_of(area - 1) + 1,
+        }
+    }
+
+    /// Returns the offset in the vec of the `i`th element of the tile.
+    fn offset_of(&self, i: usize) -> usize {
+        let x = self.rect.x + i % self.rect.width;
+        let y = self.rect.y + i / self.rect.width;
+        y * self.stride + x
+    }
+}
@@ -0,0 +1,31 @@
+//! Events emitted via `tracing` when the `tracing` feature is enabled. Without the feature, these
+//! functions do nothing.
+
+use crate::InitError;
+use std::ops::Range;
+
+pub(crate) fn shard_taken(range: &Range<usize>) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(?range, "Took shard");
+    #[cfg(not(feature = "tracing"))]
+    let _ = range;
+}
+
+pub(crate) fn shard_returned(range: &Range<usize>) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(?range, "Returned shard");
+    #[cfg(not(feature = "tracing"))]
+    let _ = range;
+}
+
+pub(crate) fn return_failed(error: &InitError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(%error, "Failed to return shard");
+    #[cfg(not(feature = "tracing"))]
+    let _ = error;
+}
+
+pub(crate) fn unfinished_writer_dropped() {
+    #[cfg(feature = "tracing")]
+    tracing::error!("VecWriter dropped after taking shards without `finish` being called");
+}
@@ -0,0 +1,132 @@
+use crate::InitError;
+use crate::Shard;
+use crate::VecWriter;
+use std::collections::BTreeMap;
+
+/// A hash function that can hash the contents of each shard independently, then combine the
+/// resulting digests into a single digest of the whole output. This avoids a second pass over the
+/// output after it has been built.
+///
+/// Note that the final digest depends on where the shard boundaries are, not just on the contents
+/// of the output, so consumers that compare digests need to use the same partitioning.
+pub trait TreeHasher<T> {
+    type Digest;
+
+    /// Hashes the contents of a single shard.
+    fn hash_leaf(&self, values: &[T]) -> Self::Digest;
+
+    /// Combines the digests of two adjacent parts of the output.
+    fn hash_node(&self, left: &Self::Digest, right: &Self::Digest) -> Self::Digest;
+}
+
+impl<T> Shard<'_, T> {
+    /// Hashes the values written to the shard so far. This would normally be called by the worker
+    /// once it has finished filling the shard. The resulting digest can be passed to
+    /// `HashingWriter::return_shard_with_digest`, or carried with the shard by other means, e.g. as
+    /// the summary of a `SummaryShard`, then combined with the digests of the other shards using
+    /// `tree_hash`.
+    pub fn digest<H: TreeHasher<T>>(&self, hasher: &H) -> H::Digest {
+        hasher.hash_leaf(self.as_slice())
+    }
+}
+
+/// A writer that records a digest of each shard as it's returned, then combines the digests into a
+/// single digest of the whole output when it's finished, using `tree_hash`.
+pub struct HashingWriter<'vec, T, H: TreeHasher<T>> {
+    writer: VecWriter<'vec, T>,
+    hasher: H,
+
+    /// The digest of each shard that has been returned, keyed by the shard's start offset.
+    digests: BTreeMap<usize, H::Digest>,
+}
+
+impl<'vec, T, H: TreeHasher<T>> HashingWriter<'vec, T, H> {
+    /// Creates a new writer that will write into the supplied `Vec`, hashing with `hasher`. Any
+    /// values already in the vec aren't included in the digest.
+    pub fn new(storage: &'vec mut Vec<T>, hasher: H) -> Self {
+        Self {
+            writer: VecWriter::new(storage),
+            hasher,
+            digests: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the hasher, e.g. so that workers can hash their own shards with `Shard::digest`.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Takes the next `n` elements of the vector or panics if there is insufficient capacity.
+    pub fn take_shard(&mut self, n: usize) -> Shard<'vec, T> {
+        self.writer.take_shard(n)
+    }
+
+    /// Takes the next `n` elements of the vector or returns None if there is insufficient capacity.
+    pub fn try_take_shard(&mut self, n: usize) -> Option<Shard<'vec, T>> {
+        self.writer.try_take_shard(n)
+    }
+
+    /// Hashes the contents of a shard, then returns it to the vector. See
+    /// `VecWriter::return_shard`. Panics on failure.
+    #[track_caller]
+    pub fn return_shard(&mut self, shard: Shard<T>) {
+        self.try_return_shard(shard).unwrap()
+    }
+
+    /// As for `return_shard`, but returns an error on failure rather than panicking.
+    pub fn try_return_shard(&mut self, shard: Shard<T>) -> Result<(), InitError> {
+        let digest = shard.digest(&self.hasher);
+        self.try_return_shard_with_digest(shard, digest)
+    }
+
+    /// Returns a shard together with a digest of its contents that the worker computed with
+    /// `Shard::digest`, so that hashing happens on the worker's thread rather than the thread
+    /// returning the shard. Panics on failure.
+    #[track_caller]
+    pub fn return_shard_with_digest(&mut self, shard: Shard<T>, digest: H::Digest) {
+        self.try_return_shard_with_digest(shard, digest).unwrap()
+    }
+
+    /// As for `return_shard_with_digest`, but returns an error on failure rather than panicking.
+    /// On failure, the digest is discarded.
+    pub fn try_return_shard_with_digest(
+        &mut self,
+        shard: Shard<T>,
+        digest: H::Digest,
+    ) -> Result<(), InitError> {
+        let start = shard.start_offset;
+        self.writer.try_return_shard(shard)?;
+        self.digests.insert(start, digest);
+        Ok(())
+    }
+
+    /// Checks that all shards that were taken have been returned, then combines the digests of the
+    /// shards, in output order, into a digest of the whole output. Returns None if no shards were
+    /// returned.
+    pub fn finish(self) -> Result<Option<H::Digest>, InitError> {
+        self.writer.finish()?;
+        Ok(tree_hash(&self.hasher, self.digests.into_values()))
+    }
+}
+
+/// Combines the digests of each shard, which must be in output order, into a single digest by
+/// hashing adjacent pairs until only one remains. Returns None if there are no digests.
+pub fn tree_hash<T, H: TreeHasher<T>>(
+    hasher: &H,
+    leaves: impl IntoIterator<Item = H::Digest>,
+) -> Option<H::Digest> {
+    let mut level: Vec<H::Digest> = leaves.into_iter().collect();
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len().div_ceil(2));
+        let mut digests = level.into_iter();
+        while let Some(left) = digests.next() {
+            match digests.next() {
+                Some(right) => next.push(hasher.hash_node(&left, &right)),
+                // An odd digest out is promoted to the next level unchanged.
+                None => next.push(left),
+            }
+        }
+        level = next;
+    }
+    level.pop()
+}
@@ -0,0 +1,46 @@
+use crate::parallel::available_threads;
+use crate::parallel::map_in_parallel;
+use std::string::FromUtf8Error;
+
+/// Don't bother splitting validation between threads for less than this many bytes.
+const MIN_BYTES_PER_THREAD: usize = 64 * 1024;
+
+/// Converts `bytes` into a `String`, validating UTF-8 in parallel. This is intended for large
+/// outputs built with a `VecWriter<u8>`. The bytes are split into chunks, each of which is
+/// validated separately, with chunk boundaries adjusted so that they don't fall inside a multi-byte
+/// character. On failure, the error is the same as from `String::from_utf8`.
+pub fn par_string_from_utf8(bytes: Vec<u8>) -> Result<String, FromUtf8Error> {
+    let chunk_size = bytes
+        .len()
+        .div_ceil(available_threads())
+        .max(MIN_BYTES_PER_THREAD);
+    let mut chunks = Vec::new();
+    let mut rest = bytes.as_slice();
+    while rest.len() > chunk_size {
+        let mut split = chunk_size;
+        // Move the split forward past any continuation bytes. A character is at most 4 bytes, so if
+        // there are more than 3 continuation bytes, the input is invalid, which validation of the
+        // following chunk will detect.
+        while split < chunk_size + 3 && split < rest.len() && is_continuation_byte(rest[split]) {
+            split += 1;
+        }
+        let (chunk, remainder) = rest.split_at(split);
+        chunks.push(chunk);
+        rest = remainder;
+    }
+    chunks.push(rest);
+
+    let all_valid = map_in_parallel(chunks, |chunk| std::str::from_utf8(chunk).is_ok())
+        .into_iter()
+        .all(|valid| valid);
+    if !all_valid {
+        // Redo the validation serially in order to get an error with the correct position.
+        return String::from_utf8(bytes);
+    }
+    // Safety: Every chunk is valid UTF-8 and chunks are split on character boundaries.
+    Ok(unsafe { String::from_utf8_unchecked(bytes) })
+}
+
+fn is_continuation_byte(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
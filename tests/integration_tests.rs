@@ -1,7 +1,54 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+use sharded_vec_writer::compress_frames;
+use sharded_vec_writer::decode_frames;
+use sharded_vec_writer::morton_index;
+use sharded_vec_writer::par_atomic_vec;
+use sharded_vec_writer::par_concat;
+use sharded_vec_writer::par_gather;
+use sharded_vec_writer::par_repeat;
+use sharded_vec_writer::par_scatter;
+use sharded_vec_writer::par_sort_into;
+use sharded_vec_writer::par_string_from_utf8;
+use sharded_vec_writer::par_string_table;
+use sharded_vec_writer::return_channel;
+use sharded_vec_writer::sleb128_len;
+use sharded_vec_writer::testing::TestWriter;
+use sharded_vec_writer::tree_hash;
+use sharded_vec_writer::uleb128_len;
+use sharded_vec_writer::Boundaries;
+use sharded_vec_writer::CapacityMismatch;
+use sharded_vec_writer::FinishError;
+use sharded_vec_writer::Frame;
+use sharded_vec_writer::GroupReturnError;
+use sharded_vec_writer::HashingWriter;
+use sharded_vec_writer::IncrementalBuild;
 use sharded_vec_writer::InitError;
 use sharded_vec_writer::InsufficientCapacity;
+use sharded_vec_writer::MortonWriter;
+use sharded_vec_writer::MultiOutputWriter;
+use sharded_vec_writer::MultiVecWriter;
+use sharded_vec_writer::PatchError;
+use sharded_vec_writer::PublishedPrefix;
+use sharded_vec_writer::Rect;
+use sharded_vec_writer::ScatterError;
+use sharded_vec_writer::ScratchPool;
+use sharded_vec_writer::Shard;
+use sharded_vec_writer::ShardGroup;
+use sharded_vec_writer::ShardId;
+use sharded_vec_writer::ShardQueue;
+use sharded_vec_writer::ShardSummary;
+use sharded_vec_writer::ShardedStringWriter;
+use sharded_vec_writer::StealRequest;
+use sharded_vec_writer::SummaryWriter;
+use sharded_vec_writer::TileWriter;
+use sharded_vec_writer::TreeHasher;
+use sharded_vec_writer::UninitStorage;
+use sharded_vec_writer::VecBuilder;
 use sharded_vec_writer::VecWriter;
 use std::rc::Rc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 #[test]
 fn basic_usage() {
@@ -72,7 +119,7 @@ fn return_to_wrong_vec() {
 
     assert_eq!(
         writer1.try_return_shard(shard2).unwrap_err(),
-        InitError::WrongVec
+        InitError::WrongVec { range: 0..8 }
     );
     writer1.return_shard(shard1);
 }
@@ -91,11 +138,17 @@ fn missing_shard() {
         shard2.push(i);
     }
 
+    // Shards can be returned out of order, but the vec doesn't grow until the gap is filled.
+    writer.return_shard(shard2);
+    let shard3 = writer.take_shard(2);
+    assert_eq!(writer.snapshot_initialised(), []);
+    writer.return_shard(shard1);
     assert_eq!(
-        writer.try_return_shard(shard2).unwrap_err(),
-        InitError::OutOfOrder
+        writer.finish(),
+        Err(InitError::OutOfOrder { missing: 8..10 })
     );
-    writer.return_shard(shard1);
+    drop(shard3);
+    assert_eq!(v, [0, 1, 2, 3, 0, 1, 2, 3]);
 }
 
 #[test]
@@ -110,7 +163,10 @@ fn not_fully_initialised() {
 
     assert_eq!(
         writer.try_return_shard(shard1).unwrap_err(),
-        InitError::UninitElements
+        InitError::UninitElements {
+            range: 0..4,
+            uninit: 2
+        }
     );
 }
 
@@ -155,3 +211,2909 @@ fn drop_without_returning() {
     drop(shard1);
     assert_eq!(Rc::strong_count(&r), 1);
 }
+
+#[test]
+fn aligned_records() {
+    let mut v = Vec::with_capacity(16);
+    let mut writer: VecWriter<u8> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(3);
+    let mut shard2 = writer.take_shard(13);
+
+    for i in 1..=3 {
+        shard1.push(i);
+    }
+    let offset = shard2.write_record(4, |w| {
+        w.push(4);
+        w.push(5);
+    });
+    assert_eq!(offset, 4);
+    let offset = shard2.write_record(8, |w| {
+        for i in 6..=9 {
+            w.push(i);
+        }
+    });
+    assert_eq!(offset, 8);
+    assert!(shard2.try_pad_to_alignment(32).is_err());
+    shard2.pad_to_alignment(16);
+
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(v, [1, 2, 3, 0, 4, 5, 0, 0, 6, 7, 8, 9, 0, 0, 0, 0]);
+}
+
+#[test]
+fn section_addresses() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer: VecWriter<u8> = VecWriter::new(&mut v);
+    let mut text = writer.take_section(6, 0x1000);
+    let mut data = writer.take_section(4, 0x4000);
+
+    assert_eq!(text.address(), 0x1000);
+    text.push(1);
+    text.push(2);
+    assert_eq!(text.address(), 0x1002);
+    assert_eq!(data.address_of_offset(8), Some(0x4002));
+    assert_eq!(data.address_of_offset(10), Some(0x4004));
+    assert_eq!(data.address_of_offset(2), None);
+    for i in 3..=6 {
+        text.push(i);
+    }
+    for i in 7..=10 {
+        data.push(i);
+    }
+
+    writer.return_section(text);
+    writer.return_shard(data.into_shard());
+    assert_eq!(v, (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn deferred_patches() {
+    let mut v = Vec::with_capacity(8);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4);
+    let mut shard2 = writer.take_shard(4);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..4 {
+                shard1.push(i);
+            }
+            shard1.queue_patch(6, vec![100, 101]);
+        });
+        scope.spawn(|| {
+            for i in 4..8 {
+                shard2.push(i);
+            }
+            shard2.queue_patch(0, vec![200]);
+        });
+    });
+    writer.return_shard(shard1);
+    assert_eq!(writer.apply_patches(), Err(PatchError::OutstandingShards));
+    writer.return_shard(shard2);
+    writer.apply_patches().unwrap();
+    assert_eq!(v, [200, 1, 2, 3, 4, 5, 100, 101]);
+}
+
+#[test]
+fn invalid_patches() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    for i in 0..4 {
+        shard.push(i);
+    }
+    shard.queue_patch(0, vec![10, 11]);
+    shard.queue_patch(1, vec![12]);
+    writer.return_shard(shard);
+    assert_eq!(
+        writer.apply_patches(),
+        Err(PatchError::Overlap { offset: 1 })
+    );
+
+    let mut shard = writer.try_take_shard(0).unwrap();
+    shard.queue_patch(3, vec![1, 2]);
+    writer.return_shard(shard);
+    assert_eq!(
+        writer.apply_patches(),
+        Err(PatchError::OutOfBounds { offset: 3, len: 2 })
+    );
+}
+
+#[test]
+fn crc32_trailer() {
+    let mut v = Vec::with_capacity(13);
+    let mut writer: VecWriter<u8> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4);
+    let mut shard2 = writer.take_shard(5);
+    let trailer = writer.take_crc32_trailer();
+    for &byte in b"1234" {
+        shard1.push(byte);
+    }
+    for &byte in b"56789" {
+        shard2.push(byte);
+    }
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(writer.return_crc32_trailer(trailer), Ok(0xcbf4_3926));
+    assert_eq!(v[9..], 0xcbf4_3926_u32.to_le_bytes());
+}
+
+#[test]
+fn multiple_outputs() {
+    let mut main = Vec::with_capacity(4);
+    let mut debug = Vec::with_capacity(2);
+    let mut writer: MultiOutputWriter<u8> = MultiOutputWriter::new();
+    let main_id = writer.add_output(&mut main);
+    let debug_id = writer.add_output(&mut debug);
+
+    let mut main_shard = writer.take_shard(main_id, 4);
+    let mut debug_shard = writer.take_shard(debug_id, 2);
+    assert!(writer.try_take_shard(debug_id, 1).is_none());
+    for i in 0..4 {
+        main_shard.push(i);
+    }
+    debug_shard.push(10);
+    debug_shard.push(11);
+    writer.return_shard(debug_shard);
+    writer.return_shard(main_shard);
+    writer.finish().unwrap();
+
+    assert_eq!(main, [0, 1, 2, 3]);
+    assert_eq!(debug, [10, 11]);
+}
+
+#[test]
+fn multiple_outputs_incomplete() {
+    let mut main = Vec::with_capacity(4);
+    let mut debug = Vec::with_capacity(2);
+    let mut writer: MultiOutputWriter<u8> = MultiOutputWriter::new();
+    writer.add_output(&mut main);
+    let debug_id = writer.add_output(&mut debug);
+    let _shard = writer.take_shard(debug_id, 2);
+    assert_eq!(writer.finish().unwrap_err().output, debug_id);
+}
+
+#[test]
+fn decode_framed_input() {
+    // Each frame is a sequence of little-endian u16s.
+    let input: Vec<u8> = (0..10u16).flat_map(|i| i.to_le_bytes()).collect();
+    let frames = [
+        Frame {
+            input: 0..6,
+            num_elements: 3,
+        },
+        Frame {
+            input: 6..8,
+            num_elements: 1,
+        },
+        Frame {
+            input: 8..20,
+            num_elements: 6,
+        },
+    ];
+    let decode = |bytes: &[u8], shard: &mut sharded_vec_writer::Shard<u16>| {
+        for pair in bytes.chunks_exact(2) {
+            shard.push(u16::from_le_bytes([pair[0], pair[1]]));
+        }
+    };
+    let decoded = decode_frames(&input, &frames, decode).unwrap();
+    assert_eq!(decoded, (0..10).collect::<Vec<_>>());
+
+    let short_frames = [Frame {
+        input: 0..4,
+        num_elements: 3,
+    }];
+    assert_eq!(
+        decode_frames(&input, &short_frames, decode),
+        Err(InitError::UninitElements {
+            range: 0..3,
+            uninit: 1
+        })
+    );
+}
+
+#[test]
+fn tiles() {
+    let mut v = Vec::with_capacity(12);
+    let mut writer: TileWriter<u32> = TileWriter::new(&mut v, 3, 3, 4);
+    let left = Rect {
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 3,
+    };
+    let right = Rect {
+        x: 2,
+        y: 0,
+        width: 1,
+        height: 3,
+    };
+    let mut left_tile = writer.take_tile(left);
+    let mut right_tile = writer.take_tile(right);
+    assert!(writer.try_take_tile(left).is_none());
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while let Some((x, y)) = left_tile.position() {
+                left_tile.push((y * 10 + x) as u32);
+            }
+        });
+        scope.spawn(|| {
+            for y in 0..3 {
+                right_tile.push_row(&[y * 10 + 2]);
+            }
+        });
+    });
+    writer.return_tile(right_tile);
+    writer.return_tile(left_tile);
+    writer.finish().unwrap();
+    assert_eq!(v, [0, 1, 2, 0, 10, 11, 12, 0, 20, 21, 22, 0]);
+}
+
+#[test]
+fn tiles_incomplete() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer: TileWriter<Rc<()>> = TileWriter::new(&mut v, 2, 2, 2);
+    let r = Rc::new(());
+    let mut tile = writer.take_tile(Rect {
+        x: 0,
+        y: 1,
+        width: 2,
+        height: 1,
+    });
+    tile.push(Rc::clone(&r));
+    assert_eq!(
+        writer.try_return_tile(tile).unwrap_err(),
+        InitError::UninitElements {
+            range: 2..4,
+            uninit: 1
+        }
+    );
+    assert_eq!(Rc::strong_count(&r), 1);
+    assert_eq!(
+        writer.finish().unwrap_err(),
+        InitError::UninitElements {
+            range: 0..4,
+            uninit: 4
+        }
+    );
+}
+
+#[test]
+fn stencil_with_halo() {
+    let input: Vec<i32> = (0..10).map(|i| i * i).collect();
+    let mut v = Vec::with_capacity(10);
+    let mut writer: VecWriter<i32> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4);
+    let mut shard2 = writer.take_shard(6);
+    assert_eq!(shard1.input_with_halo(&input, 1).input_range(), 0..5);
+    assert_eq!(shard2.input_with_halo(&input, 1).input_range(), 3..10);
+
+    std::thread::scope(|scope| {
+        for (shard, range) in [(&mut shard1, 0..4_usize), (&mut shard2, 4..10)] {
+            let input = &input;
+            scope.spawn(move || {
+                let view = shard.input_with_halo(input, 1);
+                for i in range {
+                    let sum = [i.checked_sub(1), Some(i), Some(i + 1)]
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|j| view.get(j))
+                        .sum();
+                    shard.push(sum);
+                }
+            });
+        }
+    });
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+
+    let expected: Vec<i32> = (0..10_usize)
+        .map(|i| input[i.saturating_sub(1)..(i + 2).min(10)].iter().sum())
+        .collect();
+    assert_eq!(v, expected);
+}
+
+#[test]
+fn morton_order() {
+    let mut v = Vec::with_capacity(16);
+    let mut writer: MortonWriter<(usize, usize)> = MortonWriter::new(&mut v, 2);
+    let mut big = writer.take_block(1);
+    let mut small = writer.take_block(0);
+    assert!(writer.try_take_block(1).is_none());
+    let mut rest = vec![
+        writer.take_block(0),
+        writer.take_block(0),
+        writer.take_block(0),
+    ];
+    rest.push(writer.take_block(1));
+    rest.push(writer.take_block(1));
+    assert!(writer.try_take_block(0).is_none());
+
+    for block in std::iter::once(&mut big)
+        .chain(std::iter::once(&mut small))
+        .chain(&mut rest)
+    {
+        loop {
+            let position = block.position();
+            if block.try_push(position).is_err() {
+                break;
+            }
+        }
+    }
+    writer.return_block(big);
+    writer.return_block(small);
+    for block in rest {
+        writer.return_block(block);
+    }
+    writer.finish().unwrap();
+
+    for x in 0..4 {
+        for y in 0..4 {
+            assert_eq!(v[morton_index(x, y)], (x, y));
+        }
+    }
+    assert_eq!(v[..4], [(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+    // Capacity beyond the grid isn't handed out.
+    let mut v = Vec::with_capacity(20);
+    let mut writer = MortonWriter::new(&mut v, 1);
+    let mut block = writer.take_block(0);
+    block.push(0);
+    writer.return_block(block);
+    assert_eq!(writer.finish(), Err(FinishError { missing: 1..4 }));
+    let mut v = Vec::with_capacity(20);
+    let mut writer = MortonWriter::new(&mut v, 1);
+    assert!(writer.try_take_block(2).is_none());
+    let mut block = writer.take_block(1);
+    block.fill(1);
+    assert!(writer.try_take_block(0).is_none());
+    writer.return_block(block);
+    writer.finish().unwrap();
+    assert_eq!(v.len(), 4);
+}
+
+#[test]
+fn shard_summaries() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer: SummaryWriter<u32, (u32, u32)> = SummaryWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4, (u32::MAX, 0));
+    let mut shard2 = writer.take_shard(6, (u32::MAX, 0));
+    let min_max = |summary: &mut (u32, u32), value: &u32| {
+        *summary = (summary.0.min(*value), summary.1.max(*value));
+    };
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for value in [5, 3, 9, 4] {
+                shard1.push_folded(value, min_max);
+            }
+        });
+        scope.spawn(|| {
+            for value in [12, 10, 15, 11, 10, 13] {
+                shard2.push_folded(value, min_max);
+            }
+        });
+    });
+    assert_eq!(*shard1.summary(), (3, 9));
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(
+        writer.finish().unwrap(),
+        [
+            ShardSummary {
+                range: 0..4,
+                summary: (3, 9)
+            },
+            ShardSummary {
+                range: 4..10,
+                summary: (10, 15)
+            },
+        ]
+    );
+}
+
+#[test]
+fn tagged_shards() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_tagged_shard(2, "first.o");
+    let mut shard2 = writer.take_tagged_shard(2, "second.o");
+    shard1.push(1);
+    shard1.push(2);
+    shard2.push(3);
+
+    let err = writer.try_return_tagged_shard(shard2).unwrap_err();
+    assert_eq!(err.tag, "second.o");
+    assert_eq!(
+        err.error,
+        InitError::UninitElements {
+            range: 2..4,
+            uninit: 1
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Elements not initialised in shard 2..4 (1 uninitialised) (shard \"second.o\")"
+    );
+    assert_eq!(*shard1.tag(), "first.o");
+    assert_eq!(writer.return_tagged_shard(shard1), "first.o");
+}
+
+struct TestHasher;
+
+impl TreeHasher<u8> for TestHasher {
+    type Digest = u64;
+
+    fn hash_leaf(&self, values: &[u8]) -> u64 {
+        use std::hash::Hash;
+        use std::hash::Hasher;
+        let mut hasher = std::hash::DefaultHasher::new();
+        values.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_node(&self, left: &u64, right: &u64) -> u64 {
+        left.rotate_left(5) ^ right.wrapping_mul(31)
+    }
+}
+
+#[test]
+fn tree_hash_of_shards() {
+    let mut v = Vec::with_capacity(9);
+    let mut writer: SummaryWriter<u8, u64> = SummaryWriter::new(&mut v);
+    let mut shards: Vec<_> = [3, 4, 2]
+        .into_iter()
+        .map(|n| writer.take_shard(n, 0))
+        .collect();
+    std::thread::scope(|scope| {
+        for (i, shard) in shards.iter_mut().enumerate() {
+            scope.spawn(move || {
+                while shard.try_push(i as u8).is_ok() {}
+                *shard.summary_mut() = shard.digest(&TestHasher);
+            });
+        }
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    let digests = writer.finish().unwrap().into_iter().map(|s| s.summary);
+    let digest = tree_hash(&TestHasher, digests).unwrap();
+
+    let h = TestHasher;
+    let expected = h.hash_node(
+        &h.hash_node(&h.hash_leaf(&[0, 0, 0]), &h.hash_leaf(&[1, 1, 1, 1])),
+        &h.hash_leaf(&[2, 2]),
+    );
+    assert_eq!(digest, expected);
+    assert_eq!(tree_hash(&TestHasher, []), None);
+}
+
+#[test]
+fn hashing_writer() {
+    let mut v = Vec::with_capacity(9);
+    let mut writer = HashingWriter::new(&mut v, TestHasher);
+    let mut shards: Vec<_> = [3, 4, 2].map(|n| writer.take_shard(n)).into();
+    for (i, shard) in shards.iter_mut().enumerate() {
+        shard.fill(i as u8);
+    }
+    let last = shards.pop().unwrap();
+    let digest = last.digest(writer.hasher());
+    writer.return_shard_with_digest(last, digest);
+    for shard in shards.into_iter().rev() {
+        writer.return_shard(shard);
+    }
+
+    let h = TestHasher;
+    let expected = h.hash_node(
+        &h.hash_node(&h.hash_leaf(&[0, 0, 0]), &h.hash_leaf(&[1, 1, 1, 1])),
+        &h.hash_leaf(&[2, 2]),
+    );
+    assert_eq!(writer.finish(), Ok(Some(expected)));
+
+    let mut v = Vec::with_capacity(2);
+    let mut writer = HashingWriter::new(&mut v, TestHasher);
+    let _shard = writer.take_shard(2);
+    assert_eq!(
+        writer.finish(),
+        Err(InitError::OutOfOrder { missing: 0..2 })
+    );
+}
+
+#[test]
+fn return_processor() {
+    let mut v = Vec::with_capacity(6);
+    let mut writer: VecWriter<u16> = VecWriter::new(&mut v);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    writer.set_return_processor(move |range, values| {
+        for value in values.iter_mut() {
+            *value = value.swap_bytes();
+        }
+        sender.send(range).unwrap();
+    });
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(4);
+    shard1.push(0x0102);
+    shard1.push(0x0304);
+    for i in 0..4 {
+        shard2.push(i);
+    }
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0..2, 2..6]);
+    assert_eq!(v, [0x0201, 0x0403, 0, 0x100, 0x200, 0x300]);
+}
+
+#[test]
+fn return_validator() {
+    let mut v = Vec::with_capacity(9);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    writer.set_return_validator(|_, values| {
+        if values.is_sorted() {
+            Ok(())
+        } else {
+            Err("not sorted")
+        }
+    });
+    let mut shard = writer.take_shard(3);
+    shard.copy_from_slice(&[1, 2, 3]);
+    writer.return_shard(shard);
+    let mut shard = writer.take_shard(3);
+    shard.copy_from_slice(&[5, 4, 6]);
+    assert_eq!(
+        writer.try_return_shard(shard),
+        Err(InitError::ValidationFailed {
+            range: 3..6,
+            message: "not sorted".to_owned()
+        })
+    );
+
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    writer.set_return_validator(|range, values| {
+        if values.iter().all(|&value| value as usize >= range.start) {
+            Ok(())
+        } else {
+            Err(format!("value below {}", range.start))
+        }
+    });
+    let mut shards = writer.take_shards_by_sizes(&[3, 3]);
+    shards[0].copy_from_slice(&[3, 4, 5]);
+    shards[1].copy_from_slice(&[5, 7, 8]);
+    let error = writer.try_return_shards(shards).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Shard 6..9 failed validation: value below 6"
+    );
+    assert_eq!(writer.initialised(), [1, 2, 3]);
+}
+
+#[test]
+fn snapshot() {
+    let mut v = vec![100];
+    v.reserve_exact(4);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(2);
+    shard1.push(1);
+    shard1.push(2);
+    shard2.push(3);
+    assert_eq!(writer.snapshot_initialised(), [100]);
+    writer.return_shard(shard1);
+    assert_eq!(writer.snapshot_initialised(), [100, 1, 2]);
+    shard2.push(4);
+    writer.return_shard(shard2);
+    assert_eq!(writer.snapshot_initialised(), [100, 1, 2, 3, 4]);
+}
+
+#[test]
+fn largest_first() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer: VecWriter<usize> = VecWriter::new(&mut v);
+    assert!(writer.take_shards_largest_first(&[5, 6]).is_none());
+    let mut shards = writer.take_shards_largest_first(&[2, 5, 1, 2]).unwrap();
+    assert_eq!(
+        shards.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+        [1, 0, 3, 2]
+    );
+    for (index, shard) in &mut shards {
+        while shard.try_push(*index).is_ok() {}
+    }
+    shards.sort_by_key(|(index, _)| *index);
+    for (_, shard) in shards {
+        writer.return_shard(shard);
+    }
+    assert_eq!(v, [0, 0, 1, 1, 1, 1, 1, 2, 3, 3]);
+}
+
+#[test]
+fn gather() {
+    let src: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+    let gathered = par_gather(&src, &[4_u32, 0, 0, 2]);
+    assert_eq!(gathered, ["4", "0", "0", "2"]);
+    assert!(par_gather(&src, &[] as &[usize]).is_empty());
+}
+
+#[test]
+#[should_panic = "Index at position 1 is out of bounds for source of length 3"]
+fn gather_out_of_bounds() {
+    par_gather(&[1, 2, 3], &[0_usize, 3]);
+}
+
+#[test]
+fn scatter() {
+    let src = [10, 20, 30, 40];
+    let scattered = par_scatter(&src, &[2, 0, 3, 1], |v| v.to_string()).unwrap();
+    assert_eq!(scattered, ["20", "40", "10", "30"]);
+
+    assert_eq!(
+        par_scatter(&src, &[2, 0, 2, 1], |v| *v),
+        Err(ScatterError::Duplicate {
+            position: 2,
+            destination: 2
+        })
+    );
+    assert_eq!(
+        par_scatter(&src, &[2, 0, 4, 1], |v| *v),
+        Err(ScatterError::OutOfBounds {
+            position: 2,
+            destination: 4
+        })
+    );
+    assert_eq!(
+        par_scatter(&src, &[0], |v| *v),
+        Err(ScatterError::LengthMismatch {
+            src_len: 4,
+            perm_len: 1
+        })
+    );
+}
+
+#[test]
+fn sort_into() {
+    // Items compare by key only, so that we can check that the sort is stable.
+    #[derive(Debug, Clone)]
+    struct Item {
+        key: u32,
+        id: u32,
+    }
+    impl PartialEq for Item {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+    impl Eq for Item {}
+    impl PartialOrd for Item {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Item {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    let input: Vec<Item> = (0..1000)
+        .map(|id| Item {
+            key: (id * 7919) % 101,
+            id,
+        })
+        .collect();
+    let mut expected = input.clone();
+    expected.sort();
+
+    let mut output = vec![Item { key: 500, id: 0 }];
+    par_sort_into(input, &mut output);
+    assert_eq!(output.len(), 1001);
+    assert_eq!(
+        output[1..]
+            .iter()
+            .map(|i| (i.key, i.id))
+            .collect::<Vec<_>>(),
+        expected.iter().map(|i| (i.key, i.id)).collect::<Vec<_>>()
+    );
+
+    let mut empty = Vec::new();
+    par_sort_into(Vec::<u32>::new(), &mut empty);
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn sort_into_with_inconsistent_ord() {
+    // Keys beat each other like rock-paper-scissors, so there's no consistent order.
+    #[derive(Debug)]
+    struct Item {
+        key: u32,
+        id: u32,
+    }
+    impl PartialEq for Item {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+    impl Eq for Item {}
+    impl PartialOrd for Item {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Item {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            if self.key == other.key {
+                std::cmp::Ordering::Equal
+            } else if (other.key + 5 - self.key) % 5 <= 2 {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Greater
+            }
+        }
+    }
+
+    for len in 0..=20 {
+        let input: Vec<Item> = (0..len)
+            .map(|id| Item {
+                key: id * 3 % 5,
+                id,
+            })
+            .collect();
+        let mut output = Vec::new();
+        par_sort_into(input, &mut output);
+        // The order is unspecified, but each value must be moved exactly once.
+        let mut ids: Vec<u32> = output.iter().map(|item| item.id).collect();
+        ids.sort();
+        assert_eq!(ids, (0..len).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn c_api() {
+    use sharded_vec_writer::ffi::*;
+
+    unsafe {
+        let writer = svw_writer_new(5);
+        let shard1 = svw_writer_take_shard(writer, 2);
+        let shard2 = svw_writer_take_shard(writer, 3);
+        assert!(svw_writer_take_shard(writer, 1).is_null());
+
+        assert_eq!(
+            svw_shard_push_bytes(shard2, b"cdef".as_ptr(), 4),
+            SvwStatus::InsufficientCapacity
+        );
+        assert_eq!(
+            svw_shard_push_bytes(shard2, b"cde".as_ptr(), 3),
+            SvwStatus::Ok
+        );
+        assert_eq!(
+            svw_shard_push_bytes(shard1, b"ab".as_ptr(), 2),
+            SvwStatus::Ok
+        );
+
+        let (mut data, mut len, mut capacity) = (std::ptr::null_mut(), 0, 0);
+        assert_eq!(
+            svw_writer_finish(writer, &mut data, &mut len, &mut capacity),
+            SvwStatus::OutstandingShards
+        );
+        assert_eq!(svw_writer_return_shard(writer, shard1), SvwStatus::Ok);
+        assert_eq!(svw_writer_return_shard(writer, shard2), SvwStatus::Ok);
+        assert_eq!(
+            svw_writer_finish(writer, &mut data, &mut len, &mut capacity),
+            SvwStatus::Ok
+        );
+        assert_eq!(std::slice::from_raw_parts(data, len), b"abcde");
+        svw_buffer_free(data, len, capacity);
+    }
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn c_api_errors() {
+    use sharded_vec_writer::ffi::*;
+
+    unsafe {
+        let writer = svw_writer_new(4);
+        let shard1 = svw_writer_take_shard(writer, 2);
+        let shard2 = svw_writer_take_shard(writer, 2);
+        assert_eq!(
+            svw_writer_return_shard(writer, shard2),
+            SvwStatus::UninitElements
+        );
+        assert_eq!(
+            svw_shard_push_bytes(shard1, std::ptr::null(), 1),
+            SvwStatus::NullPointer
+        );
+        svw_shard_free(shard1);
+
+        // Both shards are gone, so nothing is outstanding, but the buffer can never be completed.
+        let (mut data, mut len, mut capacity) = (std::ptr::null_mut(), 0, 0);
+        assert_eq!(
+            svw_writer_finish(writer, &mut data, &mut len, &mut capacity),
+            SvwStatus::OutOfOrder
+        );
+        let (mut start, mut end) = (0, 0);
+        assert_eq!(
+            svw_writer_missing_range(writer, &mut start, &mut end),
+            SvwStatus::OutOfOrder
+        );
+        assert_eq!((start, end), (0, 4));
+        svw_writer_free(writer);
+    }
+}
+
+#[test]
+fn string_from_utf8() {
+    // Large enough to be split into multiple chunks, with multi-byte characters throughout.
+    let text = "aé€😀".repeat(100_000);
+    let bytes = text.clone().into_bytes();
+    assert_eq!(par_string_from_utf8(bytes).unwrap(), text);
+
+    let mut bytes = text.into_bytes();
+    bytes[500_001] = 0xff;
+    let error = par_string_from_utf8(bytes.clone()).unwrap_err();
+    assert_eq!(error, String::from_utf8(bytes).unwrap_err());
+}
+
+#[test]
+fn injected_failures() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer: TestWriter<u32> = TestWriter::new(&mut v)
+        .fail_take(1)
+        .limit_pushes(2, 1)
+        .lose_shard(3)
+        .limit_pushes(4, 2);
+    let mut shard1 = writer.take_shard(2);
+    assert!(writer.try_take_shard(2).is_none());
+    let mut shard2 = writer.take_shard(2);
+    let mut shard3 = writer.take_shard(2);
+
+    shard1.push(1);
+    shard1.push(2);
+    shard2.push(3);
+    assert_eq!(shard2.try_push(4), Err(InsufficientCapacity));
+    shard3.push(5);
+    shard3.push(6);
+
+    writer.return_shard(shard1);
+    assert_eq!(
+        writer.try_return_shard(shard2),
+        Err(InitError::UninitElements {
+            range: 2..4,
+            uninit: 1
+        })
+    );
+    writer.return_shard(shard3);
+
+    // A limit that's no smaller than the shard has no effect.
+    let mut shard4 = writer.take_shard(2);
+    shard4.push(7);
+    shard4.push(8);
+    writer.return_shard(shard4);
+    assert_eq!(v, [1, 2]);
+}
+
+#[test]
+fn published_prefix() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    shard.push(1);
+    shard.push(2);
+    let prefix: PublishedPrefix<u32> = shard.publish_prefix();
+    let consumer = std::thread::scope(|scope| {
+        let handle = scope.spawn(|| prefix.iter().sum::<u32>());
+        shard.push(3);
+        shard.push(4);
+        handle.join().unwrap()
+    });
+    assert_eq!(consumer, 3);
+    assert_eq!(prefix.range(), 0..2);
+    drop(prefix);
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 3, 4]);
+}
+
+#[test]
+fn return_with_published_prefix() {
+    let mut v = Vec::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(1);
+    shard.push(String::from("a"));
+    let prefix = shard.publish_prefix();
+    assert_eq!(
+        writer.try_return_shard(shard),
+        Err(InitError::PrefixStillPublished { range: 0..1 })
+    );
+    // The shard was dropped, but the published value must still be readable.
+    assert_eq!(&prefix[0], "a");
+}
+
+#[test]
+fn split_after_publishing_prefix() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    shard.push(String::from("a"));
+    shard.push(String::from("b"));
+    let prefix = shard.publish_prefix();
+    assert_eq!(prefix.len(), 2);
+    drop(prefix);
+    let (head, mut tail) = shard.split_at(1);
+    tail.push(String::from("c"));
+    tail.push(String::from("d"));
+    writer.return_shard(head);
+    writer.return_shard(tail);
+    assert_eq!(v, ["a", "b", "c", "d"]);
+}
+
+#[test]
+fn second_pass_with_boundaries() {
+    let mut v = vec![0];
+    v.reserve(5);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(3);
+    shard1.push(1);
+    shard1.push(2);
+    shard2.push(3);
+    shard2.push(4);
+    shard2.push(5);
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    let boundaries: Boundaries = writer.finish().unwrap();
+    assert_eq!(boundaries.ranges(), [1..3, 3..6]);
+    assert_eq!(boundaries.shard_containing(0), None);
+    assert_eq!(boundaries.shard_containing(2), Some(0));
+    assert_eq!(boundaries.shard_containing(3), Some(1));
+    assert_eq!(boundaries.shard_containing(6), None);
+
+    let chunks = boundaries.split_mut(&mut v);
+    assert_eq!(chunks, [&mut [1, 2][..], &mut [3, 4, 5][..]]);
+
+    boundaries.par_for_each_mut(&mut v, |index, chunk| {
+        chunk.iter_mut().for_each(|value| *value += index * 10);
+    });
+    assert_eq!(v, [0, 1, 2, 13, 14, 15]);
+}
+
+#[test]
+fn finish_with_outstanding_shard() {
+    let mut v = Vec::<u32>::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    let _shard = writer.take_shard(1);
+    assert_eq!(
+        writer.finish(),
+        Err(InitError::OutOfOrder { missing: 0..1 })
+    );
+}
+
+#[test]
+fn fill_in_slices() {
+    use std::task::Poll;
+
+    let mut v = Vec::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(5);
+    let mut next = 0;
+    let mut f = || {
+        next += 1;
+        next
+    };
+    assert_eq!(shard.fill_with_budget(2, &mut f), Poll::Pending);
+    assert_eq!(shard.fill_with_budget(2, &mut f), Poll::Pending);
+    assert_eq!(shard.fill_with_budget(2, &mut f), Poll::Ready(()));
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 3, 4, 5]);
+
+    let input = [7, 8, 9];
+    let mut v = Vec::with_capacity(3);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(3);
+    let mut position = 0;
+    assert_eq!(
+        shard.extend_from_slice_with_budget(&input, &mut position, 2),
+        Poll::Pending
+    );
+    assert_eq!(position, 2);
+    assert_eq!(
+        shard.extend_from_slice_with_budget(&input, &mut position, 2),
+        Poll::Ready(())
+    );
+    writer.return_shard(shard);
+    assert_eq!(v, input);
+}
+
+#[test]
+fn exact_capacity() {
+    let mut v = Vec::<u8>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let _shard = writer.take_shard(4);
+    assert_eq!(writer.ensure_exact_capacity(6), Ok(()));
+    assert_eq!(
+        writer.ensure_exact_capacity(5),
+        Err(CapacityMismatch {
+            expected: 5,
+            actual: 6
+        })
+    );
+}
+
+#[test]
+fn wait_for_shard_to_be_returned() {
+    let mut v = Vec::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let watcher = writer.return_watcher();
+    let mut shard1 = writer.take_shard(1);
+    let mut shard2 = writer.take_shard(1);
+    let id: ShardId = shard1.id();
+    assert!(!watcher.is_returned(id));
+    std::thread::scope(|scope| {
+        let waiter = scope.spawn(|| watcher.await_returned(id));
+        shard1.push(1);
+        writer.return_shard(shard1);
+        assert!(waiter.join().unwrap());
+        shard2.push(2);
+        let id2 = shard2.id();
+        drop(shard2);
+        drop(writer);
+        assert!(!watcher.await_returned(id2));
+    });
+    assert_eq!(v, [1]);
+}
+
+#[test]
+fn scratch_buffers_are_recycled() {
+    let pool = ScratchPool::new();
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut allocations = Vec::new();
+    for i in 0..2 {
+        let (mut shard, mut scratch) = writer.take_shard_with_scratch(2, &pool, 100);
+        assert!(scratch.is_empty());
+        assert!(scratch.capacity() >= 100);
+        allocations.push(scratch.as_ptr());
+        scratch.extend([i * 2, i * 2 + 1]);
+        for value in scratch.drain(..) {
+            shard.push(value);
+        }
+        drop(scratch);
+        writer.return_shard(shard);
+    }
+    assert_eq!(allocations[0], allocations[1]);
+    assert_eq!(v, [0, 1, 2, 3]);
+}
+
+#[test]
+fn repeat_template() {
+    assert_eq!(par_repeat(&[1, 2, 3], 3), [1, 2, 3, 1, 2, 3, 1, 2, 3]);
+    assert!(par_repeat(&[1, 2, 3], 0).is_empty());
+    assert!(par_repeat::<u8>(&[], 5).is_empty());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic = "without `finish` being called"]
+fn dropped_without_finish() {
+    let mut v = Vec::<u32>::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    writer.require_finish();
+    let mut shard = writer.take_shard(1);
+    shard.push(1);
+    writer.return_shard(shard);
+}
+
+#[test]
+fn finish_when_required() {
+    let mut v = Vec::<u32>::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    writer.require_finish();
+    let mut shard = writer.take_shard(1);
+    shard.push(1);
+    writer.return_shard(shard);
+    writer.finish().unwrap();
+    assert_eq!(v, [1]);
+}
+
+#[test]
+fn leb128() {
+    let unsigned = [0, 2, 127, 128, 624485, u64::MAX];
+    let signed = [0, 2, -2, 63, 64, -64, -65, -123456, i64::MIN, i64::MAX];
+    let total = unsigned.iter().map(|&v| uleb128_len(v)).sum::<usize>()
+        + signed.iter().map(|&v| sleb128_len(v)).sum::<usize>();
+    let mut v = Vec::with_capacity(total);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(total);
+    for value in unsigned {
+        shard.write_uleb128(value);
+    }
+    for value in signed {
+        shard.write_sleb128(value);
+    }
+    assert_eq!(shard.try_write_uleb128(0), Err(InsufficientCapacity));
+    writer.return_shard(shard);
+    assert_eq!(&v[..7], [0, 2, 0x7f, 0x80, 0x01, 0xe5, 0x8e]);
+    assert_eq!(v.len(), total);
+    assert_eq!(uleb128_len(u64::MAX), 10);
+    assert_eq!(sleb128_len(-123456), 3);
+    assert_eq!(sleb128_len(64), 2);
+    assert_eq!(sleb128_len(-64), 1);
+}
+
+#[test]
+fn string_table() {
+    let table = par_string_table(&["main", "foo", "main", "", "bar", "foo"]);
+    assert_eq!(table.bytes, b"main\0foo\0\0bar\0");
+    assert_eq!(table.offsets.len(), 4);
+    assert_eq!(table.offsets["main"], 0);
+    assert_eq!(table.offsets["foo"], 5);
+    assert_eq!(table.offsets[""], 9);
+    assert_eq!(table.offsets["bar"], 10);
+    assert!(par_string_table(&[]).bytes.is_empty());
+}
+
+#[test]
+fn atomic_vec() {
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+
+    let atomics: Vec<AtomicU32> = par_atomic_vec(5, |index| index as u32 * 2);
+    atomics[1].fetch_add(1, Ordering::Relaxed);
+    let values: Vec<u32> = atomics.iter().map(|a| a.load(Ordering::Relaxed)).collect();
+    assert_eq!(values, [0, 3, 4, 6, 8]);
+}
+
+#[test]
+fn steal_tail() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(10);
+    let steal = StealRequest::new();
+    shard.push(0);
+    shard.push(1);
+    assert!(steal.check_in(&mut shard).is_none());
+    steal.request();
+    let mut tail = steal.check_in(&mut shard).unwrap();
+    assert!(steal.check_in(&mut shard).is_none());
+    assert_eq!(tail.output_offset(), 6);
+    std::thread::scope(|scope| {
+        scope.spawn(|| (6..10).for_each(|i| tail.push(i)));
+        (2..6).for_each(|i| shard.push(i));
+    });
+    assert_eq!(shard.try_push(6), Err(InsufficientCapacity));
+    assert!(shard.split_off_tail(1).is_none());
+    writer.return_shard(shard);
+    writer.return_shard(tail);
+    assert_eq!(v, (0..10).collect::<Vec<_>>());
+}
+
+sharded_vec_writer::shard_plan! {
+    struct TestLayout: [u32; 3] {
+        first: 1,
+        rest: 2,
+    }
+}
+
+#[test]
+fn fixed_layout() {
+    let mut v = Vec::with_capacity(TestLayout::CAPACITY);
+    let mut writer = VecWriter::new(&mut v);
+    let mut layout = TestLayout::take(&mut writer);
+    layout.rest.push(2);
+    layout.rest.push(3);
+    layout.first.push(1);
+    layout.return_all(&mut writer);
+    assert_eq!(v, [1, 2, 3]);
+}
+
+#[test]
+fn push_directly_on_writer() {
+    let mut v = Vec::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    writer.push(1);
+    let mut shard = writer.take_shard(2);
+    shard.push(2);
+    shard.push(3);
+    writer.return_shard(shard);
+    writer.extend([4, 5]);
+    assert_eq!(writer.try_push(6), Err(InsufficientCapacity));
+    assert_eq!(v, [1, 2, 3, 4, 5]);
+}
+
+#[test]
+#[should_panic = "shards are outstanding"]
+fn push_with_outstanding_shard() {
+    let mut v = Vec::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let _shard = writer.take_shard(1);
+    writer.push(1);
+}
+
+#[test]
+fn incremental_build() {
+    use std::task::Poll;
+    use std::time::Duration;
+
+    let mut v = Vec::with_capacity(6);
+    let writer = VecWriter::new(&mut v);
+    let mut build = IncrementalBuild::new(writer, vec![1, 2, 3], |index, shard| {
+        while shard.try_push(index).is_ok() {}
+    });
+    assert_eq!(build.step(Duration::ZERO), Poll::Pending);
+    assert_eq!(build.step(Duration::ZERO), Poll::Pending);
+    assert_eq!(build.step(Duration::ZERO), Poll::Ready(()));
+    assert_eq!(build.step(Duration::MAX), Poll::Ready(()));
+    build.into_writer().finish().unwrap();
+    assert_eq!(v, [0, 1, 1, 2, 2, 2]);
+}
+
+#[test]
+fn seekable_writes() {
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    let mut v = vec![0];
+    v.reserve_exact(7);
+    let mut writer = VecWriter::new(&mut v);
+    let mut out = writer.seekable();
+    out.seek(SeekFrom::Start(3)).unwrap();
+    out.write_all(&[3, 4]).unwrap();
+    out.seek(SeekFrom::Start(1)).unwrap();
+    out.write_all(&[1]).unwrap();
+    assert!(out.write(&[0]).is_ok());
+    out.seek(SeekFrom::Start(0)).unwrap();
+    assert!(out.write(&[9]).is_err());
+    out.seek(SeekFrom::Current(2)).unwrap();
+    out.write_all(&[2]).unwrap();
+    out.finish().unwrap();
+
+    let mut shard = writer.take_shard(3);
+    for value in 5..8 {
+        shard.push(value);
+    }
+    writer.return_shard(shard);
+    assert_eq!(v, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut out = writer.seekable();
+    out.seek(SeekFrom::End(-1)).unwrap();
+    out.write_all(&[1]).unwrap();
+    assert_eq!(out.write(&[2]).unwrap(), 0);
+    assert_eq!(
+        out.finish(),
+        Err(InitError::UninitElements {
+            range: 0..4,
+            uninit: 3
+        })
+    );
+    assert!(v.is_empty());
+
+    // Once a partial shard has been returned, nothing more can be written.
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(2);
+    shard.push(1);
+    writer.return_partial_shard(shard);
+    let mut out = writer.seekable();
+    assert_eq!(out.write(&[2]).unwrap(), 0);
+    assert_eq!(out.seek(SeekFrom::End(0)).unwrap(), 1);
+    out.finish().unwrap();
+    assert_eq!(v, [1]);
+
+    // Writing after seeking past the capacity writes nothing.
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut out = writer.seekable();
+    out.seek(SeekFrom::Start(1 << 40)).unwrap();
+    assert_eq!(out.write(&[]).unwrap(), 0);
+    assert_eq!(out.write(&[1]).unwrap(), 0);
+    out.seek(SeekFrom::End(10)).unwrap();
+    assert_eq!(out.write(&[1]).unwrap(), 0);
+    out.finish().unwrap();
+    assert!(v.is_empty());
+}
+
+#[test]
+fn compress_then_decode_frames() {
+    // A simple run-length encoding, with each run stored as a (count, value) pair.
+    let input = [1, 1, 1, 2, 3, 3, 3, 3, 3, 4];
+    let (compressed, frames) = compress_frames(
+        &input,
+        4,
+        |len| len * 2,
+        |chunk, shard| {
+            for run in chunk.chunk_by(|a, b| a == b) {
+                shard.push(run.len() as u8);
+                shard.push(run[0]);
+            }
+        },
+    );
+    assert_eq!(compressed, [3, 1, 1, 2, 4, 3, 1, 3, 1, 4]);
+    let decoded = decode_frames(&compressed, &frames, |bytes, shard| {
+        for pair in bytes.chunks(2) {
+            for _ in 0..pair[0] {
+                shard.push(pair[1]);
+            }
+        }
+    })
+    .unwrap();
+    assert_eq!(decoded, input);
+}
+
+#[test]
+fn return_group_atomically() {
+    let mut values = Vec::with_capacity(4);
+    let mut lengths = Vec::with_capacity(4);
+    let mut writer = MultiOutputWriter::new();
+    let values_id = writer.add_output(&mut values);
+    let lengths_id = writer.add_output(&mut lengths);
+
+    let mut shard1 = writer.take_shard(values_id, 2);
+    let mut shard2 = writer.take_shard(lengths_id, 2);
+    shard1.push(10);
+    shard1.push(20);
+    shard2.push(1);
+    let mut group = ShardGroup::new();
+    group.push(shard1);
+    group.push(shard2);
+    assert_eq!(
+        writer.try_return_group(group),
+        Err(GroupReturnError::Shard {
+            index: 1,
+            error: InitError::UninitElements {
+                range: 0..2,
+                uninit: 1
+            }
+        })
+    );
+
+    let mut shard1 = writer.take_shard(values_id, 2);
+    let mut shard2 = writer.take_shard(lengths_id, 1);
+    shard1.push(30);
+    shard1.push(40);
+    shard2.push(2);
+    let mut group = ShardGroup::new();
+    group.require_equal_lengths();
+    group.push(shard1);
+    group.push(shard2);
+    assert_eq!(
+        writer.try_return_group(group),
+        Err(GroupReturnError::LengthMismatch)
+    );
+    drop(writer);
+    assert!(values.is_empty());
+    assert!(lengths.is_empty());
+
+    let mut v = Vec::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(1);
+    let mut shard2 = writer.take_shard(1);
+    shard1.push(1);
+    shard2.push(2);
+    let mut group = ShardGroup::new();
+    group.push(shard1);
+    group.push(shard2);
+    writer.return_group(group);
+    assert_eq!(v, [1, 2]);
+}
+
+#[test]
+fn return_group_with_rejecting_validator() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer: VecWriter<u32> = VecWriter::new(&mut v);
+    writer.set_return_validator(|range, _| {
+        if range.start == 0 {
+            Ok(())
+        } else {
+            Err("rejected")
+        }
+    });
+    let mut group = ShardGroup::new();
+    for mut shard in writer.take_shards_by_sizes(&[2, 2]) {
+        shard.fill(1);
+        group.push(shard);
+    }
+    assert_eq!(
+        writer.try_return_group(group),
+        Err(GroupReturnError::Shard {
+            index: 1,
+            error: InitError::ValidationFailed {
+                range: 2..4,
+                message: "rejected".to_owned()
+            }
+        })
+    );
+    assert!(writer.initialised().is_empty());
+}
+
+#[test]
+fn bulk_slice_writes() {
+    let mut v = Vec::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(5);
+    shard.copy_from_slice(&[1, 2, 3]);
+    assert_eq!(
+        shard.try_copy_from_slice(&[4, 5, 6]),
+        Err(InsufficientCapacity)
+    );
+    shard.copy_from_slice(&[4, 5]);
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 3, 4, 5]);
+
+    let mut v = Vec::with_capacity(3);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(3);
+    let strings = [String::from("a"), String::from("b")];
+    shard.extend_from_slice(&strings);
+    assert_eq!(
+        shard.try_extend_from_slice(&strings),
+        Err(InsufficientCapacity)
+    );
+    shard.extend_from_slice(&strings[..1]);
+    writer.return_shard(shard);
+    assert_eq!(v, ["a", "b", "a"]);
+}
+
+#[test]
+fn fill_remaining() {
+    let mut v = Vec::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(3);
+    let mut shard2 = writer.take_shard(3);
+    shard1.push(String::from("a"));
+    shard1.fill(String::from("b"));
+    shard1.fill(String::from("c"));
+    let mut next = 0;
+    shard2.fill_with(|| {
+        next += 1;
+        next.to_string()
+    });
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(v, ["a", "b", "b", "1", "2", "3"]);
+}
+
+#[test]
+fn return_shards_in_any_order() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards: Vec<_> = (0..4).map(|_| Some(writer.take_shard(1))).collect();
+    for (i, shard) in shards.iter_mut().enumerate() {
+        shard.as_mut().unwrap().push(i.to_string());
+    }
+    for i in [2, 3, 0, 1] {
+        writer.return_shard(shards[i].take().unwrap());
+    }
+    drop(shards);
+    let boundaries = writer.finish().unwrap();
+    assert_eq!(boundaries.ranges(), [0..1, 1..2, 2..3, 3..4]);
+    assert_eq!(v, ["0", "1", "2", "3"]);
+}
+
+#[test]
+fn workers_return_their_own_shards() {
+    let mut v = Vec::with_capacity(8);
+    let mut writer = VecWriter::new(&mut v);
+    let (returner, collector) = return_channel();
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let mut shard = writer.take_shard(2);
+            let returner = returner.clone();
+            scope.spawn(move || {
+                shard.push(i * 2);
+                shard.push(i * 2 + 1);
+                returner.return_shard(shard);
+            });
+        }
+        writer.collect(collector).unwrap();
+    });
+    drop(returner);
+    assert_eq!(v, (0..8).collect::<Vec<_>>());
+
+    let mut v = Vec::<u32>::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    let (returner, collector) = return_channel();
+    drop(writer.take_shard(1));
+    drop(returner);
+    assert_eq!(
+        writer.collect(collector),
+        Err(InitError::OutOfOrder { missing: 0..1 })
+    );
+
+    // A shard taken from the back that's still outstanding isn't mistaken for the output being
+    // complete.
+    let mut v = Vec::<u32>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let (returner, collector) = return_channel();
+    let mut front = writer.take_shard(6);
+    let _back = writer.take_shard_back(2);
+    front.fill(1);
+    returner.return_shard(front);
+    drop(returner);
+    assert_eq!(
+        writer.collect(collector),
+        Err(InitError::OutOfOrder { missing: 8..10 })
+    );
+    assert!(writer.outstanding_ranges().eq(std::iter::once(8..10)));
+}
+
+#[test]
+fn split_partially_written_shard() {
+    let mut v = Vec::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(6);
+    shard.push(0);
+    shard.push(1);
+    shard.push(2);
+    let (mut head, mut tail) = shard.split_at(2);
+    assert_eq!(head.try_push(9), Err(InsufficientCapacity));
+    assert_eq!(tail.output_offset(), 3);
+    let mut last = tail.split_off(2);
+    tail.push(3);
+    last.push(4);
+    last.push(5);
+    writer.return_shard(last);
+    writer.return_shard(tail);
+    writer.return_shard(head);
+    assert_eq!(v, [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn shard_chunks() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(10);
+    shard.push(0);
+    let mut chunks: Vec<_> = shard.chunks(4).collect();
+    assert_eq!(
+        chunks.iter().map(Shard::range).collect::<Vec<_>>(),
+        [0..4, 4..8, 8..10]
+    );
+    std::thread::scope(|scope| {
+        for chunk in &mut chunks {
+            scope.spawn(|| chunk.fill_with_index(|i| i));
+        }
+    });
+    writer.return_shards(chunks);
+    assert_eq!(v, (0..10).collect::<Vec<_>>());
+
+    let mut v = Vec::<u32>::new();
+    let mut writer = VecWriter::new(&mut v);
+    assert_eq!(writer.take_shard(0).chunks(3).count(), 0);
+}
+
+#[test]
+fn take_remaining_capacity() {
+    let mut v = Vec::<u32>::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_remaining();
+    assert!(writer.take_remaining().try_push(0).is_err());
+    shard1.fill(1);
+    shard2.fill(2);
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(v, [1, 1, 2, 2, 2]);
+}
+
+#[test]
+fn even_and_fixed_size_splits() {
+    let mut v = Vec::with_capacity(7);
+    let mut writer = VecWriter::new(&mut v);
+    let shards = writer.split_even(3);
+    let sizes: Vec<usize> = shards
+        .into_iter()
+        .map(|mut shard| {
+            let start = shard.output_offset();
+            shard.fill(0);
+            let size = shard.output_offset() - start;
+            writer.return_shard(shard);
+            size
+        })
+        .collect();
+    assert_eq!(sizes, [3, 2, 2]);
+
+    let mut v = Vec::with_capacity(7);
+    let mut writer = VecWriter::new(&mut v);
+    let shards: Vec<_> = writer.shards_of_size(3).collect();
+    assert_eq!(shards.len(), 3);
+    for (i, mut shard) in shards.into_iter().enumerate() {
+        shard.fill(i);
+        writer.return_shard(shard);
+    }
+    assert_eq!(v, [0, 0, 0, 1, 1, 1, 2]);
+}
+
+#[test]
+fn write_into_uninit_slice() {
+    use std::mem::MaybeUninit;
+
+    let mut buffer: Box<[MaybeUninit<u32>]> = Box::new_uninit_slice(4);
+    let mut storage = UninitStorage::new(&mut buffer);
+    let mut writer = VecWriter::new(&mut storage);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_remaining();
+    shard2.fill(2);
+    shard1.fill(1);
+    writer.return_shard(shard2);
+    writer.return_shard(shard1);
+    writer.finish().unwrap();
+    assert_eq!(storage.len(), 4);
+    assert_eq!(storage.initialised(), [1, 1, 2, 2]);
+}
+
+#[test]
+fn write_via_spare_capacity() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    shard.push(1_u16);
+    let spare = shard.spare_capacity_mut();
+    assert_eq!(spare.len(), 3);
+    spare[0].write(2);
+    spare[1].write(3);
+    // Safety: We just initialised the first two elements.
+    unsafe { shard.set_written(2) };
+    shard.push(4);
+    assert!(shard.spare_capacity_mut().is_empty());
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 3, 4]);
+}
+
+#[test]
+fn io_write_to_shard() {
+    use std::io::Write;
+
+    let mut v = Vec::with_capacity(8);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(8);
+    write!(shard, "{}-{}", 12, 34).unwrap();
+    assert_eq!(shard.write(b"abcdef").unwrap(), 3);
+    assert_eq!(
+        shard.write_all(b"x").unwrap_err().kind(),
+        std::io::ErrorKind::WriteZero
+    );
+    writer.return_shard(shard);
+    assert_eq!(v, b"12-34abc");
+}
+
+#[test]
+fn build_string_in_parallel() {
+    use std::fmt::Write;
+
+    let rows = ["alpha", "beta", "gamma"];
+    let sizes: Vec<usize> = rows.iter().map(|row| row.len() + 3).collect();
+    let mut s = String::with_capacity(sizes.iter().sum());
+    let mut writer = ShardedStringWriter::new(&mut s);
+    let mut shards: Vec<_> = sizes.iter().map(|&size| writer.take_shard(size)).collect();
+    std::thread::scope(|scope| {
+        for ((i, shard), row) in shards.iter_mut().enumerate().zip(rows) {
+            scope.spawn(move || {
+                write!(shard, "{i}:{row}").unwrap();
+                assert!(shard.try_push_str("no room").is_err());
+                shard.push('\n');
+                assert_eq!(shard.remaining(), 0);
+            });
+        }
+    });
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    assert_eq!(s, "0:alpha\n1:beta\n2:gamma\n");
+}
+
+#[test]
+fn reserve_mid_build() {
+    let mut v = Vec::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(2);
+    shard.fill(1);
+    writer.return_shard(shard);
+    writer.reserve(3);
+    let mut shard = writer.take_shard(3);
+    shard.fill(2);
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 1, 2, 2, 2]);
+}
+
+#[test]
+#[should_panic = "shards are outstanding"]
+fn reserve_with_outstanding_shard() {
+    let mut v = Vec::<u8>::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let _shard = writer.take_shard(1);
+    writer.reserve(10);
+}
+
+#[test]
+fn owned_builder() {
+    let mut builder = VecBuilder::with_capacity(4);
+    let mut shard1 = builder.take_shard(2);
+    let mut shard2 = builder.take_shard(2);
+    let handle = std::thread::spawn(move || {
+        shard2.fill(String::from("b"));
+        shard2
+    });
+    shard1.fill(String::from("a"));
+    builder.return_shard(shard1);
+    builder.return_shard(handle.join().unwrap());
+    assert_eq!(builder.finish().unwrap(), ["a", "a", "b", "b"]);
+
+    let mut builder = VecBuilder::<u32>::with_capacity(1);
+    let mut shard = builder.take_shard(1);
+    assert_eq!(
+        builder.finish(),
+        Err(InitError::OutOfOrder { missing: 0..1 })
+    );
+    // The builder leaked its storage, so the shard can still be written.
+    shard.push(1);
+}
+
+#[test]
+fn owned_builder_finish_boxed() {
+    let mut builder = VecBuilder::with_capacity(4);
+    let mut shard = builder.take_shard(4);
+    shard.copy_from_slice(&[1, 2, 3, 4]);
+    let ptr = shard.as_slice().as_ptr();
+    builder.return_shard(shard);
+    let values: Box<[u32]> = builder.finish_boxed().unwrap();
+    assert_eq!(*values, [1, 2, 3, 4]);
+    assert_eq!(values.as_ptr(), ptr);
+
+    let mut builder = VecBuilder::with_capacity(3);
+    builder.take_shard(3).fill(String::from("a"));
+    assert_eq!(builder.finish_shared(), Err(FinishError { missing: 0..3 }));
+
+    let mut builder = VecBuilder::with_capacity(3);
+    let mut shard = builder.take_shard(2);
+    shard.fill(String::from("a"));
+    builder.return_shard(shard);
+    assert_eq!(builder.finish_shared(), Err(FinishError { missing: 2..3 }));
+
+    let mut builder = VecBuilder::with_capacity(3);
+    let mut shards = [builder.take_shard(2), builder.take_shard(1)];
+    shards[1].push(String::from("b"));
+    shards[0].fill(String::from("a"));
+    for shard in shards {
+        builder.return_shard(shard);
+    }
+    let values = builder.finish_shared().unwrap();
+    assert_eq!(*values, ["a", "a", "b"]);
+}
+
+#[test]
+fn modify_written_values() {
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    shard.push(0);
+    shard.push(10);
+    shard.push(20);
+    assert_eq!(shard.as_slice(), [0, 10, 20]);
+    assert_eq!(shard.len(), 3);
+    shard.as_mut_slice()[0] = 3;
+    shard.iter_mut().for_each(|value| *value += 1);
+    shard.push(30);
+    writer.return_shard(shard);
+    assert_eq!(v, [4, 11, 21, 30]);
+}
+
+#[test]
+#[should_panic = "views of it are published"]
+fn modify_published_values() {
+    let mut v = Vec::with_capacity(1);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(1);
+    shard.push(1);
+    let _prefix = shard.publish_prefix();
+    shard.as_mut_slice()[0] = 2;
+}
+
+#[test]
+fn introspection() {
+    let mut v = vec![0];
+    v.reserve_exact(5);
+    let mut writer = VecWriter::new(&mut v);
+    assert_eq!(writer.taken(), 1);
+    assert_eq!(writer.remaining_capacity(), 5);
+    let mut shard = writer.take_shard(3);
+    assert_eq!(writer.taken(), 4);
+    assert_eq!(writer.remaining_capacity(), 2);
+    assert_eq!(shard.range(), 1..4);
+    assert_eq!(shard.capacity(), 3);
+    shard.push(1);
+    assert_eq!(shard.len(), 1);
+    assert_eq!(shard.remaining_capacity(), 2);
+    assert!(!shard.is_full());
+    shard.fill(2);
+    assert!(shard.is_full());
+    writer.return_shard(shard);
+}
+
+#[test]
+fn scoped_init() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    writer
+        .scope_init(3, |range, shard| {
+            for i in range {
+                shard.push(i * 2);
+            }
+        })
+        .unwrap();
+    assert_eq!(v, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+
+    let mut v = Vec::<u32>::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    assert_eq!(
+        writer.scope_init(2, |_, shard| shard.push(1)),
+        Err(InitError::UninitElements {
+            range: 0..2,
+            uninit: 1
+        })
+    );
+}
+
+#[test]
+fn clear_and_retry() {
+    let mut v = Vec::with_capacity(3);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(3);
+    let dropped = Rc::new(());
+    shard.push(Rc::clone(&dropped));
+    shard.push(Rc::clone(&dropped));
+    shard.clear();
+    assert_eq!(Rc::strong_count(&dropped), 1);
+    assert_eq!(shard.remaining_capacity(), 3);
+    shard.fill_with(|| Rc::new(()));
+    writer.return_shard(shard);
+    assert_eq!(v.len(), 3);
+}
+
+#[test]
+fn return_partial_final_shard() {
+    let mut v = Vec::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(5);
+    shard1.fill(1);
+    shard2.push(2);
+    shard2.push(3);
+    writer.return_shard(shard1);
+    writer.return_partial_shard(shard2);
+    assert_eq!(writer.remaining_capacity(), 0);
+    assert!(writer.try_take_shard(1).is_none());
+    writer.finish().unwrap();
+    assert_eq!(v, [1, 1, 2, 3]);
+
+    let mut v = Vec::<u32>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let shard1 = writer.take_shard(2);
+    let _shard2 = writer.take_shard(2);
+    assert_eq!(
+        writer.try_return_partial_shard(shard1),
+        Err(InitError::OutOfOrder { missing: 2..4 })
+    );
+}
+
+#[test]
+fn nested_sharding() {
+    let mut v = Vec::with_capacity(8);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(8);
+    shard.push(0);
+    let mut sub_writer = shard.into_writer();
+    let mut sub_shards: Vec<_> = (0..3).map(|_| sub_writer.take_shard(2)).collect();
+    std::thread::scope(|scope| {
+        for (i, sub_shard) in sub_shards.iter_mut().enumerate() {
+            scope.spawn(move || sub_shard.fill(i as u32 + 1));
+        }
+    });
+    for sub_shard in sub_shards.into_iter().rev() {
+        sub_writer.return_shard(sub_shard);
+    }
+    let mut shard = sub_writer.into_shard().unwrap();
+    shard.push(4);
+    writer.return_shard(shard);
+    assert_eq!(v, [0, 1, 1, 2, 2, 3, 3, 4]);
+}
+
+#[test]
+fn nested_shard_returned_to_wrong_writer() {
+    let mut v = Vec::<u32>::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut sub_writer = writer.take_shard(4).into_writer();
+    let mut sub_shard = sub_writer.take_shard(4);
+    sub_shard.fill(1);
+    assert_eq!(
+        writer.try_return_shard(sub_shard),
+        Err(InitError::WrongVec { range: 0..4 })
+    );
+    assert!(sub_writer.into_shard().is_err());
+}
+
+#[test]
+fn take_shards_by_sizes() {
+    let mut v = Vec::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    assert!(writer.try_take_shards_by_sizes(&[3, 4]).is_none());
+    assert_eq!(writer.taken(), 0);
+    assert!(writer.try_take_shards_by_sizes(&[usize::MAX, 1]).is_none());
+    let shards = writer.take_shards_by_sizes(&[1, 2, 3]);
+    assert_eq!(writer.remaining_capacity(), 0);
+    for (i, mut shard) in shards.into_iter().enumerate() {
+        shard.fill(i);
+        writer.return_shard(shard);
+    }
+    assert_eq!(v, [0, 1, 1, 2, 2, 2]);
+}
+
+#[test]
+fn struct_of_arrays() {
+    let mut keys = Vec::with_capacity(4);
+    let mut values = Vec::with_capacity(5);
+    let mut flags = Vec::with_capacity(4);
+    let mut writer = MultiVecWriter::new((&mut keys, &mut values, &mut flags));
+    assert_eq!(writer.remaining_capacity(), 4);
+    let mut shards = vec![writer.take_shard(1), writer.take_shard(3)];
+    assert!(writer.try_take_shard(1).is_none());
+    std::thread::scope(|scope| {
+        for (i, shard) in shards.iter_mut().enumerate() {
+            scope.spawn(move || {
+                while shard.remaining_capacity() > 0 {
+                    shard.push((i as u32, format!("v{i}"), i == 0));
+                }
+            });
+        }
+    });
+    assert_eq!(shards[1].columns().1[0], "v1");
+    for shard in shards {
+        writer.return_shard(shard);
+    }
+    drop(writer);
+    assert_eq!(keys, [0, 1, 1, 1]);
+    assert_eq!(values, ["v0", "v1", "v1", "v1"]);
+    assert_eq!(flags, [true, false, false, false]);
+}
+
+#[test]
+#[should_panic = "Columns must have the same length, but have lengths [1, 0]"]
+fn struct_of_arrays_with_unequal_lengths() {
+    let mut keys = vec![1];
+    let mut values: Vec<u32> = Vec::with_capacity(1);
+    MultiVecWriter::<(u32, u32)>::new((&mut keys, &mut values));
+}
+
+#[test]
+fn read_initialised_prefix() {
+    let mut v = vec![1];
+    v.reserve_exact(3);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(1);
+    let mut shard2 = writer.take_shard(2);
+    shard1.push(2);
+    writer.return_shard(shard1);
+    let prefix = writer.initialised();
+    std::thread::scope(|scope| {
+        scope.spawn(|| assert_eq!(prefix.iter().sum::<i32>(), 3));
+        scope.spawn(|| shard2.fill(3));
+    });
+    writer.return_shard(shard2);
+    assert_eq!(writer.initialised(), [1, 2, 3, 3]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn collect_async() {
+    use sharded_vec_writer::async_return_channel;
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::Context;
+    use std::task::Poll;
+    use std::task::Wake;
+
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    let mut v = Vec::with_capacity(20);
+    let mut writer = VecWriter::new(&mut v);
+    let (returner, collector) = async_return_channel();
+    std::thread::scope(|scope| {
+        for mut shard in writer.split_even(4) {
+            let returner = returner.clone();
+            scope.spawn(move || {
+                shard.fill(7);
+                returner.return_shard(shard);
+            });
+        }
+        drop(returner);
+        block_on(writer.collect_async(collector)).unwrap();
+    });
+    assert_eq!(v, [7; 20]);
+
+    let mut v = Vec::<u8>::with_capacity(2);
+    let mut writer = VecWriter::new(&mut v);
+    let (returner, collector) = async_return_channel();
+    let shard = writer.take_shard(2);
+    drop(shard);
+    drop(returner);
+    assert_eq!(
+        block_on(writer.collect_async(collector)),
+        Err(InitError::OutOfOrder { missing: 0..2 })
+    );
+}
+
+#[test]
+fn write_individual_slots() {
+    let mut v = Vec::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(5);
+    shard.push(0);
+    let (slot_set, slots) = shard.into_slots();
+    std::thread::scope(|scope| {
+        for slot in slots.into_iter().rev() {
+            scope.spawn(move || {
+                let value = slot.offset() * 10;
+                slot.write(value);
+            });
+        }
+    });
+    writer.return_shard(slot_set.into_shard().unwrap());
+    assert_eq!(v, [0, 10, 20, 30, 40]);
+
+    let mut v = Vec::with_capacity(3);
+    let mut writer = VecWriter::new(&mut v);
+    let (slot_set, mut slots) = writer.take_shard(3).into_slots();
+    let value = Rc::new(());
+    slots.pop().unwrap().write(Rc::clone(&value));
+    slots.pop().unwrap().write(Rc::clone(&value));
+    drop(slots);
+    assert_eq!(
+        slot_set.into_shard().err(),
+        Some(InitError::UninitElements {
+            range: 0..3,
+            uninit: 1
+        })
+    );
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn split_even_aligned() {
+    let mut v = Vec::<u64>::with_capacity(100);
+    let base = v.as_ptr() as usize;
+    let mut writer = VecWriter::new(&mut v);
+    let shards = writer.split_even_aligned(3);
+    assert_eq!(writer.remaining_capacity(), 0);
+    assert_eq!(shards.len(), 3);
+    assert_eq!(shards[0].range().start, 0);
+    assert_eq!(shards[2].range().end, 100);
+    for (i, pair) in shards.windows(2).enumerate() {
+        assert_eq!(pair[0].range().end, pair[1].range().start);
+        assert_eq!((base + pair[1].range().start * 8) % 64, 0);
+        assert!(pair[1].range().start.abs_diff(100 * (i + 1) / 3) <= 4);
+    }
+}
+
+#[test]
+fn finish_complete() {
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(4);
+    shard.fill(1);
+    writer.return_shard(shard);
+    assert!(!writer.is_complete());
+    assert_eq!(writer.finish_complete(), Err(FinishError { missing: 4..6 }));
+
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let _shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(4);
+    shard2.fill(2);
+    writer.return_shard(shard2);
+    assert_eq!(writer.finish_complete(), Err(FinishError { missing: 0..2 }));
+
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(6);
+    shard.fill(3);
+    writer.return_shard(shard);
+    assert!(writer.is_complete());
+    writer.finish_complete().unwrap();
+
+    // The error is reported rather than the finish check panicking when the writer is dropped.
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    writer.require_finish();
+    let mut shard = writer.take_shard(4);
+    shard.fill(4);
+    writer.return_shard(shard);
+    assert_eq!(writer.finish_complete(), Err(FinishError { missing: 4..6 }));
+}
+
+#[test]
+fn abort_restores_vec() {
+    let value = Rc::new(());
+    let mut v = vec![Rc::clone(&value)];
+    v.reserve_exact(6);
+    let mut writer = VecWriter::new(&mut v);
+    let signal = writer.abort_signal();
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(2);
+    let mut shard3 = writer.take_shard(2);
+    shard1.fill_with(|| Rc::clone(&value));
+    shard3.fill_with(|| Rc::clone(&value));
+    shard2.push(Rc::clone(&value));
+    writer.return_shard(shard1);
+    writer.return_shard(shard3);
+    assert_eq!(Rc::strong_count(&value), 7);
+    assert!(!signal.is_aborted());
+    writer.abort();
+    assert!(signal.is_aborted());
+    drop(shard2);
+    assert_eq!(Rc::strong_count(&value), 2);
+    assert_eq!(v.len(), 1);
+}
+
+#[test]
+fn progress_handle() {
+    let mut v = vec![0_u32];
+    v.reserve_exact(8);
+    let mut writer = VecWriter::new(&mut v);
+    let mut early = writer.take_shard(2);
+    let progress = writer.progress_handle();
+    assert_eq!(progress.total(), 8);
+    assert_eq!(progress.written(), 0);
+    let mut shard = writer.take_shard(6);
+    shard.push(1);
+    shard.copy_from_slice(&[2, 3]);
+    assert_eq!(progress.written(), 3);
+    shard.clear();
+    assert_eq!(progress.written(), 0);
+    shard.fill(4);
+    assert_eq!(progress.written(), 6);
+    writer.return_shard(shard);
+    early.fill(5);
+    assert_eq!(progress.written(), 6);
+    writer.return_shard(early);
+    assert_eq!(progress.written(), 8);
+    writer.finish().unwrap();
+}
+
+#[test]
+fn shard_raw_parts() {
+    let mut v = Vec::<u8>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(4);
+    shard1.fill(1);
+    shard2.push(2);
+    let (ptr, start, end, written) = shard2.into_raw_parts();
+    assert_eq!((start, end, written), (2, 6, 3));
+    // Simulate foreign code filling the rest of the shard.
+    unsafe { std::ptr::write_bytes(ptr.add(written), 3, end - written) };
+    let shard2 = unsafe { writer.shard_from_raw_parts(ptr, start, end, end) };
+    writer.return_shard(shard2);
+    writer.return_shard(shard1);
+    assert_eq!(v, [1, 1, 2, 3, 3, 3]);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn custom_allocator() {
+    let mut v = Vec::with_capacity_in(4, std::alloc::System);
+    v.push(1_u32);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.fill(2);
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 2, 2]);
+}
+
+#[test]
+fn try_fill_with() {
+    let mut v = Vec::<String>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(4);
+    shard1
+        .try_fill_with(|i| Ok::<_, ()>(i.to_string()))
+        .unwrap();
+    let result = shard2.try_fill_with(|i| {
+        if i == 4 {
+            Err("bad index")
+        } else {
+            Ok(i.to_string())
+        }
+    });
+    assert_eq!(result, Err("bad index"));
+    assert_eq!(shard2.as_slice(), ["2", "3"]);
+    shard2
+        .try_fill_with(|i| Ok::<_, ()>(i.to_string()))
+        .unwrap();
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(v, ["0", "1", "2", "3", "4", "5"]);
+}
+
+#[test]
+fn read_from() {
+    let data: Vec<u8> = (0..10).collect();
+    let mut v = Vec::<u8>::with_capacity(12);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4);
+    let mut shard2 = writer.take_shard(8);
+    assert_eq!(shard1.read_from(&data[..]).unwrap(), 4);
+    let error = shard2.read_exact_from(&data[4..]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(shard2.as_slice(), &data[4..]);
+    assert_eq!(shard2.read_from(&[10, 11, 12][..]).unwrap(), 2);
+    writer.return_shard(shard1);
+    writer.return_shard(shard2);
+    assert_eq!(v, (0..12).collect::<Vec<u8>>());
+}
+
+#[test]
+fn shard_extend_and_fill_from_iter() {
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.extend([1, 2]);
+    assert_eq!(
+        shard.fill_from_iter([3, 4, 5]),
+        Err(CapacityMismatch {
+            expected: 3,
+            actual: 4
+        })
+    );
+    assert_eq!(shard.len(), 2);
+    shard.fill_from_iter((3..7).map(|i| i * 10)).unwrap();
+    writer.return_shard(shard);
+    assert_eq!(v, [1, 2, 30, 40, 50, 60]);
+}
+
+#[test]
+fn shared_writer() {
+    let mut v = Vec::<usize>::with_capacity(100);
+    let mut writer = VecWriter::new(&mut v);
+    writer.require_finish();
+    let mut first = writer.take_shard(10);
+    {
+        let shared = writer.share();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while let Some(mut shard) = shared.take_up_to(7) {
+                        let start = shard.range().start;
+                        shard.try_fill_with(Ok::<_, ()>).unwrap();
+                        assert!(start >= 10);
+                        shared.return_shard(shard);
+                    }
+                });
+            }
+        });
+        assert_eq!(shared.remaining_capacity(), 0);
+        assert!(shared.try_take_shard(1).is_none());
+    }
+    first.try_fill_with(Ok::<_, ()>).unwrap();
+    writer.return_shard(first);
+    writer.finish().unwrap();
+    assert_eq!(v, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn shared_writer_returns_untaken_capacity() {
+    let mut v = Vec::<u32>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let shard1 = {
+        let shared = writer.share();
+        shared.take_shard(4)
+    };
+    assert_eq!(writer.remaining_capacity(), 6);
+    let mut shard2 = writer.take_remaining();
+    let mut shard1 = shard1;
+    shard1.fill(1);
+    shard2.fill(2);
+    writer.return_shard(shard2);
+    writer.return_shard(shard1);
+    assert_eq!(v, [1, 1, 1, 1, 2, 2, 2, 2, 2, 2]);
+}
+
+#[test]
+fn extend_from_slice_panic() {
+    struct PanicOnClone(Rc<()>, bool);
+    impl Clone for PanicOnClone {
+        fn clone(&self) -> Self {
+            assert!(!self.1, "clone failed");
+            PanicOnClone(Rc::clone(&self.0), self.1)
+        }
+    }
+
+    let value = Rc::new(());
+    let values = [
+        PanicOnClone(Rc::clone(&value), false),
+        PanicOnClone(Rc::clone(&value), false),
+        PanicOnClone(Rc::clone(&value), true),
+    ];
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        shard.extend_from_slice(&values);
+    }));
+    assert!(result.is_err());
+    assert_eq!(shard.len(), 2);
+    assert_eq!(Rc::strong_count(&value), 6);
+    drop(shard);
+    assert_eq!(Rc::strong_count(&value), 4);
+}
+
+#[test]
+fn skip_shard_default() {
+    let mut v = Vec::<u32>::with_capacity(8);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    writer.skip_shard_default(3);
+    let mut shard2 = writer.take_shard(2);
+    assert_eq!(writer.try_skip_shard_default(2), Err(InsufficientCapacity));
+    writer.skip_shard_default(1);
+    shard1.fill(1);
+    shard2.fill(2);
+    writer.return_shard(shard2);
+    writer.return_shard(shard1);
+    writer.finish().unwrap();
+    assert_eq!(v, [1, 1, 0, 0, 0, 2, 2, 0]);
+}
+
+#[test]
+fn reset_writer() {
+    let value = Rc::new(());
+    let mut v = vec![Rc::clone(&value)];
+    v.reserve_exact(4);
+    let capacity = v.capacity();
+    let mut writer = VecWriter::new(&mut v);
+    writer.require_finish();
+    for _ in 0..3 {
+        let mut shard1 = writer.take_shard(2);
+        let mut shard2 = writer.take_remaining();
+        shard1.fill_with(|| Rc::clone(&value));
+        shard2.fill_with(|| Rc::clone(&value));
+        writer.return_shard(shard2);
+        writer.return_shard(shard1);
+        assert_eq!(writer.initialised().len(), capacity);
+        writer.reset();
+        assert_eq!(Rc::strong_count(&value), 2);
+        assert_eq!(writer.initialised().len(), 1);
+    }
+    let mut shard = writer.take_remaining();
+    shard.fill_with(|| Rc::clone(&value));
+    writer.return_shard(shard);
+    writer.finish().unwrap();
+    assert_eq!(v.len(), capacity);
+}
+
+#[test]
+fn return_and_freeze() {
+    let mut v = Vec::<u32>::with_capacity(8);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(4);
+    let mut shard2 = writer.take_shard(4);
+    shard1.copy_from_slice(&[1, 2, 3, 4]);
+    let frozen = writer.return_and_freeze(shard1);
+    assert_eq!(frozen.range(), 0..4);
+    std::thread::scope(|scope| {
+        let frozen = frozen.clone();
+        scope.spawn(move || {
+            let mut sum = 0;
+            for value in frozen.iter() {
+                sum += value;
+                shard2.push(sum);
+            }
+            writer.return_shard(shard2);
+            assert_eq!(writer.apply_patches(), Err(PatchError::FrozenViews));
+            drop(frozen);
+        });
+    });
+    assert_eq!(&*frozen, [1, 2, 3, 4]);
+    drop(frozen);
+    assert_eq!(v, [1, 2, 3, 4, 1, 3, 6, 10]);
+}
+
+#[test]
+fn shard_write_at_and_index() {
+    let value = Rc::new(());
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.fill_with(|| (0, Rc::clone(&value)));
+    assert_eq!(Rc::strong_count(&value), 5);
+    shard.write_at(1, (1, Rc::new(())));
+    assert_eq!(Rc::strong_count(&value), 4);
+    shard[2].0 = 2;
+    for (i, (n, _)) in shard[3..].iter_mut().enumerate() {
+        *n = i + 3;
+    }
+    assert_eq!(shard[1].0, 1);
+    writer.return_shard(shard);
+    assert_eq!(v.iter().map(|(n, _)| *n).collect::<Vec<_>>(), [0, 1, 2, 3]);
+}
+
+#[test]
+#[should_panic(expected = "hasn't been written yet")]
+fn shard_write_at_unwritten() {
+    let mut v = Vec::<u32>::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.push(1);
+    shard.write_at(1, 2);
+}
+
+#[test]
+fn sparse_shard() {
+    let value = Rc::new(());
+    let mut v = Vec::with_capacity(100);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.push((0, Rc::clone(&value)));
+    let mut sparse = shard.into_sparse();
+    assert_eq!(sparse.range(), 1..100);
+    for i in (1..100).rev().step_by(2) {
+        sparse.write(i, (i, Rc::clone(&value)));
+    }
+    sparse.write(99, (99, Rc::clone(&value)));
+    assert_eq!(sparse.remaining(), 49);
+    assert!(sparse.is_written(97));
+    assert!(!sparse.is_written(98));
+    for i in (2..100).step_by(2) {
+        sparse.write(i, (i, Rc::clone(&value)));
+    }
+    let shard = sparse.into_shard().unwrap();
+    writer.return_shard(shard);
+    assert_eq!(Rc::strong_count(&value), 101);
+    assert!(v.iter().enumerate().all(|(i, (n, _))| i == *n));
+}
+
+#[test]
+fn sparse_shard_incomplete() {
+    let value = Rc::new(());
+    let mut v = Vec::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let mut sparse = writer.take_shard(10).into_sparse();
+    sparse.write(3, Rc::clone(&value));
+    sparse.write(7, Rc::clone(&value));
+    assert_eq!(Rc::strong_count(&value), 3);
+    assert_eq!(
+        sparse.into_shard().err(),
+        Some(InitError::UninitElements {
+            range: 0..10,
+            uninit: 8
+        })
+    );
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn return_shards() {
+    let mut v = Vec::<u32>::with_capacity(9);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards = writer.split_even(3);
+    for shard in &mut shards {
+        let start = shard.range().start as u32;
+        shard.fill_with(|| start);
+    }
+    let last = shards.pop().unwrap();
+    shards.insert(0, last);
+    let middle = shards.remove(2);
+    assert_eq!(
+        writer.try_return_shards(shards),
+        Err(InitError::OutOfOrder { missing: 3..6 })
+    );
+    assert!(writer.initialised().is_empty());
+    drop(middle);
+
+    let mut v = Vec::<u32>::with_capacity(9);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards = writer.split_even(3);
+    shards[0].fill(1);
+    shards[1].push(2);
+    shards[2].fill(3);
+    assert_eq!(
+        writer.try_return_shards(shards),
+        Err(InitError::UninitElements {
+            range: 3..6,
+            uninit: 2
+        })
+    );
+
+    let mut v = Vec::<u32>::with_capacity(9);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards = writer.split_even(3);
+    shards.reverse();
+    for (i, shard) in shards.iter_mut().enumerate() {
+        shard.fill(i as u32);
+    }
+    writer.return_shards(shards);
+    writer.finish().unwrap();
+    assert_eq!(v, [2, 2, 2, 1, 1, 1, 0, 0, 0]);
+}
+
+#[test]
+fn debug_output() {
+    let mut v = Vec::<u32>::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard1 = writer.take_shard(2);
+    let mut shard2 = writer.take_shard(3);
+    shard1.push(1);
+    shard2.fill(2);
+    assert_eq!(
+        format!("{shard1:?}"),
+        "Shard { range: 0..2, written: 1, remaining: 1, .. }"
+    );
+    writer.return_shard(shard2);
+    assert_eq!(
+        format!("{writer:?}"),
+        "VecWriter { initialised: 0, taken: 5, capacity: 6, returned_after_gap: {2: 5}, \
+         closed: false, .. }"
+    );
+    shard1.push(1);
+    writer.return_shard(shard1);
+}
+
+#[test]
+fn process_queue() {
+    let mut queue = ShardQueue::new();
+    for i in 0..20_u32 {
+        queue.push(i as usize, i);
+    }
+    assert_eq!(queue.total_size(), 190);
+    let mut v = Vec::with_capacity(190);
+    let mut writer = VecWriter::new(&mut v);
+    writer
+        .process_queue(queue, |i, shard| shard.fill(i))
+        .unwrap();
+    writer.finish().unwrap();
+    let expected: Vec<u32> = (0..20)
+        .flat_map(|i| std::iter::repeat_n(i, i as usize))
+        .collect();
+    assert_eq!(v, expected);
+
+    let mut queue = ShardQueue::new();
+    queue.push(3, 1);
+    queue.push(3, 2);
+    let mut v = Vec::with_capacity(6);
+    let mut writer = VecWriter::new(&mut v);
+    let result = writer.process_queue(queue, |i, shard| {
+        if i == 1 {
+            shard.fill(i);
+        }
+    });
+    assert_eq!(
+        result,
+        Err(InitError::UninitElements {
+            range: 3..6,
+            uninit: 3
+        })
+    );
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn write_bytes_mut() {
+    let mut buffer = bytes::BytesMut::with_capacity(8);
+    buffer.extend_from_slice(b"ab");
+    let mut writer = VecWriter::new(&mut buffer);
+    let mut shard1 = writer.take_shard(3);
+    let mut shard2 = writer.take_remaining();
+    shard2.fill(b'z');
+    shard1.copy_from_slice(b"cde");
+    writer.return_shard(shard2);
+    writer.return_shard(shard1);
+    writer.finish().unwrap();
+    let frozen: bytes::Bytes = buffer.freeze();
+    assert_eq!(&frozen[..], b"abcdezzz");
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn write_mmap() {
+    use sharded_vec_writer::MmapStorage;
+
+    let path = std::env::temp_dir().join(format!("sharded-vec-writer-{}", std::process::id()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(6).unwrap();
+    let map = unsafe { memmap2::MmapMut::map_mut(&file) }.unwrap();
+    let mut storage = MmapStorage::new(map);
+    let mut writer = VecWriter::new(&mut storage);
+    let mut shards = writer.split_even(2);
+    shards[0].copy_from_slice(b"abc");
+    shards[1].copy_from_slice(b"def");
+    writer.return_shards(shards);
+    writer.finish().unwrap();
+    assert_eq!(storage.len(), 6);
+    storage.into_inner().flush().unwrap();
+    drop(file);
+    assert_eq!(std::fs::read(&path).unwrap(), b"abcdef");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn array_shards() {
+    let mut v = Vec::<String>::with_capacity(5);
+    let mut writer = VecWriter::new(&mut v);
+    let first = writer.take_array_shard::<2>();
+    let second = writer.take_array_shard::<3>();
+    assert!(writer.try_take_array_shard::<1>().is_none());
+    assert_eq!(second.start_offset(), 2);
+    let second = second.write_all(["c", "d", "e"].map(String::from));
+    let first = first.write_all(["a", "b"].map(String::from));
+    writer.return_shards([first, second]);
+    assert_eq!(v, ["a", "b", "c", "d", "e"]);
+}
+
+#[test]
+fn push_unchecked() {
+    let mut v = Vec::<u64>::with_capacity(100);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    assert_eq!(shard.remaining_capacity(), 100);
+    for i in 0..100 {
+        unsafe { shard.push_unchecked(i * i) };
+    }
+    assert!(shard.is_full());
+    writer.return_shard(shard);
+    assert_eq!(v, (0..100).map(|i| i * i).collect::<Vec<_>>());
+}
+
+#[test]
+fn fill_with_index() {
+    let mut v = Vec::<usize>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards = writer.split_even(3);
+    shards[1].push(100);
+    for shard in &mut shards {
+        shard.fill_with_index(|i| i * 2);
+    }
+    writer.return_shards(shards);
+    assert_eq!(v, [0, 2, 4, 6, 100, 10, 12, 14, 16, 18]);
+}
+
+#[test]
+fn outstanding_ranges() {
+    let mut v = Vec::<u32>::with_capacity(10);
+    let mut writer = VecWriter::new(&mut v);
+    assert!(!writer.has_outstanding_shards());
+    let mut shards = writer.take_shards_by_sizes(&[2, 2, 2, 2]);
+    for shard in &mut shards {
+        shard.fill(1);
+    }
+    let mut shards = shards.into_iter();
+    let first = shards.next().unwrap();
+    let second = shards.next().unwrap();
+    writer.return_shard(shards.next().unwrap());
+    let fourth = shards.next().unwrap();
+    assert!(writer.has_outstanding_shards());
+    assert_eq!(
+        writer.outstanding_ranges().collect::<Vec<_>>(),
+        [0..4, 6..8]
+    );
+    writer.return_shard(first);
+    writer.return_shard(fourth);
+    assert!(writer.outstanding_ranges().eq(std::iter::once(2..4)));
+    writer.return_shard(second);
+    assert_eq!(writer.outstanding_ranges().count(), 0);
+    assert!(!writer.has_outstanding_shards());
+}
+
+#[test]
+fn shard_run_catches_panic() {
+    let value = Rc::new(());
+    let mut v = Vec::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let shard1 = writer.take_shard(2);
+    let shard2 = writer.take_shard(2);
+    let error = shard2
+        .run(|shard| {
+            shard.push(Rc::clone(&value));
+            panic!("bad input");
+        })
+        .err()
+        .unwrap();
+    assert_eq!(error.range, 2..4);
+    assert_eq!(error.message(), Some("bad input"));
+    assert_eq!(
+        error.to_string(),
+        "Worker for shard 2..4 panicked: bad input"
+    );
+    assert_eq!(Rc::strong_count(&value), 1);
+    let shard1 = shard1
+        .run(|shard| shard.fill_with(|| Rc::clone(&value)))
+        .unwrap();
+    writer.return_shard(shard1);
+    assert_eq!(writer.initialised().len(), 2);
+}
+
+#[test]
+fn zero_sized_types() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Clone, Default)]
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut v = Vec::new();
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_shard(2);
+    shard.push(Counted);
+    drop(shard);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+    let mut v = vec![Counted];
+    let mut writer = VecWriter::new(&mut v);
+    assert_eq!(writer.remaining_capacity(), usize::MAX - 1);
+    let mut shard1 = writer.take_shard(3);
+    shard1.fill(Counted);
+    writer.return_shard(shard1);
+    let mut shard = writer.take_remaining();
+    shard.extend_from_slice(&[Counted, Counted]);
+    writer.return_partial_shard(shard);
+    assert_eq!(writer.remaining_capacity(), 0);
+    writer.finish().unwrap();
+    assert_eq!(v.len(), 6);
+    let drops_before = DROPS.load(Ordering::Relaxed);
+    drop(v);
+    assert_eq!(DROPS.load(Ordering::Relaxed) - drops_before, 6);
+}
+
+#[test]
+fn append_parts() {
+    let parts: Vec<Vec<String>> = (0..10)
+        .map(|i| (0..i).map(|j| format!("{i}.{j}")).collect())
+        .collect();
+    let expected: Vec<String> = parts.concat();
+    assert_eq!(par_concat(parts.clone()), expected);
+
+    let mut v = vec!["header".to_owned()];
+    let mut writer = VecWriter::new(&mut v);
+    writer.append_parts(parts);
+    writer.reserve(1);
+    writer.push("footer".to_owned());
+    writer.finish().unwrap();
+    assert_eq!(v.len(), expected.len() + 2);
+    assert_eq!(v[1..v.len() - 1], expected);
+}
+
+#[test]
+fn shard_append() {
+    let mut v = Vec::<String>::with_capacity(3);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    let mut values = vec!["a".to_owned(), "b".to_owned()];
+    shard.append(&mut values);
+    assert!(values.is_empty());
+    let mut values = vec!["c".to_owned(), "d".to_owned()];
+    assert_eq!(shard.try_append(&mut values), Err(InsufficientCapacity));
+    assert_eq!(values.len(), 2);
+    values.pop();
+    shard.append(&mut values);
+    writer.return_shard(shard);
+    assert_eq!(v, ["a", "b", "c"]);
+}
+
+#[test]
+fn finish_compacted() {
+    let mut v = vec!["start".to_owned()];
+    v.reserve_exact(20);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shards = writer.take_shards_by_sizes(&[5, 5, 5, 5]);
+    for (i, shard) in shards.iter_mut().enumerate() {
+        for j in 0..i + 1 {
+            shard.push(format!("{i}.{j}"));
+        }
+    }
+    let [first, second, third, mut fourth] = shards.try_into().ok().unwrap();
+    fourth.push("3.4".to_owned());
+    writer.return_underfilled_shard(third);
+    writer.return_shard(fourth);
+    assert!(writer.outstanding_ranges().eq(std::iter::once(1..11)));
+    writer.return_underfilled_shard(first);
+    writer.return_underfilled_shard(second);
+    assert!(!writer.has_outstanding_shards());
+    writer.finish_compacted().unwrap();
+    assert_eq!(
+        v,
+        ["start", "0.0", "1.0", "1.1", "2.0", "2.1", "2.2", "3.0", "3.1", "3.2", "3.3", "3.4"]
+    );
+
+    let mut v = Vec::<u32>::with_capacity(4);
+    let mut writer = VecWriter::new(&mut v);
+    let _first = writer.take_shard(2);
+    let second = writer.take_shard(2);
+    writer.return_underfilled_shard(second);
+    assert_eq!(
+        writer.finish_compacted(),
+        Err(InitError::OutOfOrder { missing: 0..2 })
+    );
+}
+
+#[test]
+fn take_shard_back() {
+    let values: Vec<u32> = (0..10).collect();
+    let mut v = Vec::with_capacity(values.len());
+    let mut writer = VecWriter::new(&mut v);
+    let (even, odd): (Vec<u32>, Vec<u32>) = values.iter().partition(|&&value| value % 2 == 0);
+    let mut front = writer.take_shard(even.len());
+    let mut back = writer.take_shard_back(odd.len() - 1);
+    assert_eq!(back.range(), 6..10);
+    assert_eq!(writer.remaining_capacity(), 1);
+    front.copy_from_slice(&even);
+    back.copy_from_slice(&odd[1..]);
+    writer.return_shard(back);
+    writer.return_shard(front);
+    assert_eq!(writer.outstanding_ranges().count(), 0);
+    assert_eq!(writer.finish_complete(), Err(FinishError { missing: 5..6 }));
+
+    let mut writer = VecWriter::new(&mut v);
+    let mut back = writer.take_shard_back(4);
+    back.copy_from_slice(&odd[1..]);
+    writer.return_shard(back);
+    assert!(writer.try_take_shard_back(2).is_none());
+    writer.push(odd[0]);
+    assert_eq!(writer.initialised(), [0, 2, 4, 6, 8, 1, 3, 5, 7, 9]);
+    assert!(writer.is_complete());
+}
+
+#[cfg(feature = "non_temporal")]
+#[test]
+fn non_temporal_stores() {
+    let values: Vec<u32> = (0..1000).collect();
+    let mut v = Vec::with_capacity(1003);
+    let mut writer = VecWriter::new(&mut v);
+    writer.push(u32::MAX);
+    let mut shard = writer.take_shard(1001);
+    shard.copy_from_slice_non_temporal(&values);
+    assert_eq!(
+        shard.try_copy_from_slice_non_temporal(&values[..2]),
+        Err(InsufficientCapacity)
+    );
+    shard.push(1000);
+    writer.return_shard(shard);
+    let mut shard = writer.take_remaining();
+    shard.fill_non_temporal(7);
+    writer.return_shard(shard);
+    assert_eq!(v[0], u32::MAX);
+    assert_eq!(v[1..1002], (0..1001).collect::<Vec<_>>());
+    assert_eq!(v[1002..], [7]);
+
+    let mut v = Vec::with_capacity(5000);
+    let mut writer = VecWriter::new(&mut v);
+    let mut shard = writer.take_remaining();
+    shard.push([0; 3]);
+    shard.fill_non_temporal([1u8, 2, 3]);
+    writer.return_shard(shard);
+    assert!(v[1..].iter().all(|&value| value == [1, 2, 3]));
+}